@@ -1,23 +1,199 @@
-use std::str;
+use crate::{digit_segments, find_corrections, Font};
 
-// Test if the account number has a valid checksum
-pub fn is_checksum_valid(account_number : &str) -> bool {
-    assert!(is_numeric(account_number), "account_number must be numeric.");
-    if account_number.len() != 9 {
-        return false;
+// Algorithm used to validate the check digits of an account number.  Shared between the threads of
+// a ParallelProcessor.
+pub trait Checksum: Sync {
+    // Test if the account number has a valid checksum
+    fn is_valid(&self, account_number : &str) -> bool;
+}
+
+// Weighted sum modulo 11 as described by the kata.  Digits are weighted 1, 2, 3... from the right.
+pub struct Mod11;
+
+// Luhn algorithm used by payment card numbers
+pub struct Luhn;
+
+// ISO 7064 MOD 97-10 as used by IBAN
+pub struct Mod97;
+
+// Verhoeff dihedral group algorithm
+pub struct Verhoeff;
+
+// Damm quasigroup algorithm
+pub struct Damm;
+
+// ABA routing transit number.  Digits are weighted 3, 7, 1 repeating from the left.
+pub struct AbaRouting;
+
+// ISO 7064 MOD 17,16 hybrid system for hexadecimal references.  Letters are read in either case.
+pub struct Mod17Hex;
+
+impl Checksum for Mod11 {
+    fn is_valid(&self, account_number : &str) -> bool {
+        let mut checksum = 0;
+        let mut coefficient : u32 = 1;
+        for ch in account_number.chars().rev() {
+            match ch.to_digit(10) {
+                Some(digit) => checksum += digit * coefficient,
+                _ => return false
+            }
+            coefficient += 1;
+        }
+
+        checksum % 11 == 0
     }
+}
 
-    let mut checksum = 0;
-    let mut coefficient : u32 = 1;
-    for ch in account_number.chars().rev() {
-        match ch.to_digit(10) {
-            Some(digit) => checksum += digit * coefficient,
-            _ => return false
+impl Checksum for Luhn {
+    fn is_valid(&self, account_number : &str) -> bool {
+        let mut checksum = 0;
+        for (index, ch) in account_number.chars().rev().enumerate() {
+            match ch.to_digit(10) {
+                Some(digit) if index % 2 == 1 => checksum += if digit > 4 { digit * 2 - 9 } else { digit * 2 },
+                Some(digit) => checksum += digit,
+                _ => return false
+            }
         }
-        coefficient += 1;
+
+        checksum % 10 == 0
     }
+}
 
-    checksum % 11 == 0
+impl Checksum for Mod97 {
+    fn is_valid(&self, account_number : &str) -> bool {
+        let mut remainder = 0;
+        for ch in account_number.chars() {
+            match ch.to_digit(10) {
+                Some(digit) => remainder = (remainder * 10 + digit) % 97,
+                _ => return false
+            }
+        }
+
+        remainder == 1
+    }
+}
+
+impl Checksum for Verhoeff {
+    fn is_valid(&self, account_number : &str) -> bool {
+        // Multiplication table of the dihedral group D5
+        const MULTIPLY : [[usize; 10]; 10] = [
+            [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+            [1, 2, 3, 4, 0, 6, 7, 8, 9, 5],
+            [2, 3, 4, 0, 1, 7, 8, 9, 5, 6],
+            [3, 4, 0, 1, 2, 8, 9, 5, 6, 7],
+            [4, 0, 1, 2, 3, 9, 5, 6, 7, 8],
+            [5, 9, 8, 7, 6, 0, 4, 3, 2, 1],
+            [6, 5, 9, 8, 7, 1, 0, 4, 3, 2],
+            [7, 6, 5, 9, 8, 2, 1, 0, 4, 3],
+            [8, 7, 6, 5, 9, 3, 2, 1, 0, 4],
+            [9, 8, 7, 6, 5, 4, 3, 2, 1, 0],
+        ];
+
+        // Permutation applied to a digit based on its position
+        const PERMUTE : [[usize; 10]; 8] = [
+            [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+            [1, 5, 7, 6, 2, 8, 3, 0, 9, 4],
+            [5, 8, 0, 3, 7, 9, 6, 1, 4, 2],
+            [8, 9, 1, 6, 0, 4, 3, 5, 2, 7],
+            [9, 4, 5, 3, 1, 2, 6, 8, 7, 0],
+            [4, 2, 8, 6, 5, 7, 3, 9, 0, 1],
+            [2, 7, 9, 3, 8, 0, 6, 4, 1, 5],
+            [7, 0, 4, 6, 9, 1, 3, 2, 5, 8],
+        ];
+
+        let mut checksum = 0;
+        for (index, ch) in account_number.chars().rev().enumerate() {
+            match ch.to_digit(10) {
+                Some(digit) => checksum = MULTIPLY[checksum][PERMUTE[index % 8][digit as usize]],
+                _ => return false
+            }
+        }
+
+        checksum == 0
+    }
+}
+
+impl Checksum for Damm {
+    fn is_valid(&self, account_number : &str) -> bool {
+        // Totally anti-symmetric quasigroup of order 10
+        const QUASIGROUP : [[usize; 10]; 10] = [
+            [0, 3, 1, 7, 5, 9, 8, 6, 4, 2],
+            [7, 0, 9, 2, 1, 5, 4, 8, 6, 3],
+            [4, 2, 0, 6, 8, 7, 1, 3, 5, 9],
+            [1, 7, 5, 0, 9, 8, 3, 4, 2, 6],
+            [6, 1, 2, 3, 0, 4, 5, 9, 7, 8],
+            [3, 6, 7, 4, 2, 0, 9, 5, 8, 1],
+            [5, 8, 6, 9, 7, 2, 0, 1, 3, 4],
+            [8, 9, 4, 5, 3, 6, 2, 0, 1, 7],
+            [9, 4, 3, 8, 6, 1, 7, 2, 0, 5],
+            [2, 5, 8, 1, 4, 3, 6, 7, 9, 0],
+        ];
+
+        let mut interim = 0;
+        for ch in account_number.chars() {
+            match ch.to_digit(10) {
+                Some(digit) => interim = QUASIGROUP[interim][digit as usize],
+                _ => return false
+            }
+        }
+
+        interim == 0
+    }
+}
+
+impl Checksum for AbaRouting {
+    fn is_valid(&self, account_number : &str) -> bool {
+        const WEIGHTS : [u32; 3] = [3, 7, 1];
+
+        let mut checksum = 0;
+        for (index, ch) in account_number.chars().enumerate() {
+            match ch.to_digit(10) {
+                Some(digit) => checksum += digit * WEIGHTS[index % 3],
+                _ => return false
+            }
+        }
+
+        checksum % 10 == 0
+    }
+}
+
+impl Checksum for Mod17Hex {
+    fn is_valid(&self, account_number : &str) -> bool {
+        let mut product = 16;
+        for ch in account_number.chars() {
+            match ch.to_digit(16) {
+                Some(digit) => {
+                    let sum = match (product + digit) % 16 { 0 => 16, sum => sum };
+                    product = sum * 2 % 17;
+                }
+                _ => return false
+            }
+        }
+
+        // The final sum is 1 exactly when the check character is right
+        product == 2
+    }
+}
+
+// Find a built-in checksum algorithm by name
+pub fn checksum_by_name(name : &str) -> Option<&'static dyn Checksum> {
+    match name {
+        "mod11" => Some(&Mod11),
+        "luhn" => Some(&Luhn),
+        "mod97" => Some(&Mod97),
+        "verhoeff" => Some(&Verhoeff),
+        "damm" => Some(&Damm),
+        "aba" => Some(&AbaRouting),
+        "mod17hex" => Some(&Mod17Hex),
+        _ => None,
+    }
+}
+
+// Test if the account number has the expected number of digits and a valid checksum.  Letters are
+// left to the checksum, so only one which handles them, such as Mod17Hex, accepts a hexadecimal
+// reference.
+pub fn is_checksum_valid(account_number : &str, digits : usize, checksum : &dyn Checksum) -> bool {
+    account_number.len() == digits && checksum.is_valid(account_number)
 }
 
 fn is_numeric(account_number : &str) -> bool {
@@ -28,23 +204,8 @@ fn is_numeric(account_number : &str) -> bool {
     }
     return true;
 }
-// For a given digit, find other digits that can be formed by flipping a single segment
-fn alternate_digits(ch : u8) -> &'static [u8] {
-    match ch as char {
-        '0' => &['8' as u8],
-        '1' => &['7' as u8],
-        '2' => &[],
-        '3' => &['9' as u8],
-        '4' => &[],
-        '5' => &['6' as u8, '9' as u8],
-        '6' => &['5' as u8, '8' as u8],
-        '7' => &['1' as u8],
-        '8' => &['0' as u8, '6' as u8, '9' as u8],
-        '9' => &['3' as u8, '5' as u8, '8' as u8],
-        _ => panic!("Character must be in range '0' .. '9'."),
-    }
-}
-
+// Find numbers with a valid checksum which differ from the account number by a single segment
+//
 // Adjacent digits
 // 0 <-> 8
 //  _   _
@@ -80,32 +241,14 @@ fn alternate_digits(ch : u8) -> &'static [u8] {
 //  _   _
 // |_| |_|
 // |_|  _|
-pub fn find_adjacent(account_number : &str) -> Vec<String> {
-    assert_eq!(account_number.len(), 9, "account_number must be exactly 9 digits.");
-    assert!(account_number.is_ascii(), "account_number must contain only characters '0' though '9'.");
-
-    let mut matches = Vec::new();
-
-    // Copy account number into mutable buffer
-    let mut buffer = [0;9];
-    buffer.copy_from_slice(account_number.as_bytes());
-
-    // For each digit position try swapping with possible alternatives and test if checksum is valid
-    for n in 0..buffer.len() {
-        let ch = buffer[n];
-        let alts = alternate_digits(ch);
-        for alt in alts {
-            buffer[n] = *alt;
-            let result = str::from_utf8(&buffer);
-            if let Ok(candidate) = result {
-                if is_checksum_valid(&candidate) {
-                    matches.push(candidate.to_string());
-                }
-            }
-        }
-        buffer[n] = ch;
-    }
-    matches
+pub fn find_adjacent(account_number : &str, checksum : &dyn Checksum) -> Vec<String> {
+    assert!(is_numeric(account_number), "account_number must contain only characters '0' though '9'.");
+
+    let register : Vec<u8> = account_number.bytes().filter_map(digit_segments).collect();
+    find_corrections(&register, &Font::decimal(), 1, 1, usize::MAX, checksum)
+        .into_iter()
+        .map(|correction| correction.account_number)
+        .collect()
 }
 
 #[cfg(test)]
@@ -113,10 +256,10 @@ mod tests {
     use super::*;
 
     fn validate_find_adjacent(account_number : &str, adjacents : Vec<&str>) {
-        let adj = find_adjacent(account_number);
+        let adj = find_adjacent(account_number, &Mod11);
         assert_eq!(adj, adjacents);
         for adjacent in adjacents {
-            assert!(is_checksum_valid(adjacent));
+            assert!(is_checksum_valid(adjacent, adjacent.len(), &Mod11));
         }
     }
 
@@ -135,18 +278,59 @@ mod tests {
         validate_find_adjacent("123456799", vec!["129456799", "123496799", "123456789"]);
         validate_find_adjacent("123456788", vec!["123456789"]);
         validate_find_adjacent("123466789", vec!["123456789", "123466709"]);
+        validate_find_adjacent("12346678", vec!["12348678", "12346578"]);
     }
 
     #[test]
     fn can_validate_checksum() {
-        assert!(!is_checksum_valid("00000019"), "checksome");
-        assert!( is_checksum_valid("000000019"), "checksome");
-        assert!(!is_checksum_valid("0000000019"), "checksome");
-
-        assert!(is_checksum_valid("000000000"), "checksome");
-        assert!(!is_checksum_valid("000000001"), "checksome");
-        assert!(is_checksum_valid("500000301"), "checksome");
-        assert!(is_checksum_valid("135802539"), "checksome");
+        assert!(!is_checksum_valid("00000019", 9, &Mod11), "checksome");
+        assert!( is_checksum_valid("000000019", 9, &Mod11), "checksome");
+        assert!(!is_checksum_valid("0000000019", 9, &Mod11), "checksome");
+
+        assert!(is_checksum_valid("000000000", 9, &Mod11), "checksome");
+        assert!(!is_checksum_valid("00000000A", 9, &Mod11), "checksome");
+        assert!(!is_checksum_valid("000000001", 9, &Mod11), "checksome");
+        assert!(is_checksum_valid("500000301", 9, &Mod11), "checksome");
+        assert!(is_checksum_valid("135802539", 9, &Mod11), "checksome");
+
+        assert!( is_checksum_valid("00000019", 8, &Mod11), "checksome");
+        assert!(!is_checksum_valid("000000019", 8, &Mod11), "checksome");
+        assert!( is_checksum_valid("000000000019", 12, &Mod11), "checksome");
+    }
+
+    #[test]
+    fn can_validate_alternate_checksums() {
+        assert!( Luhn.is_valid("79927398713"));
+        assert!(!Luhn.is_valid("79927398710"));
+        assert!( Luhn.is_valid("4111111111111111"));
+
+        assert!( Mod97.is_valid("3214282912345698765432161182"));
+        assert!(!Mod97.is_valid("3214282912345698765432161183"));
+
+        assert!( Verhoeff.is_valid("2363"));
+        assert!(!Verhoeff.is_valid("2364"));
+        assert!( Verhoeff.is_valid("1428570"));
+
+        assert!( Damm.is_valid("5724"));
+        assert!(!Damm.is_valid("5727"));
+
+        assert!( AbaRouting.is_valid("011000015"));
+        assert!( AbaRouting.is_valid("111000025"));
+        assert!(!AbaRouting.is_valid("111000026"));
+
+        assert!( Mod17Hex.is_valid("ABCDEF019"));
+        assert!( Mod17Hex.is_valid("abcdef019"));
+        assert!(!Mod17Hex.is_valid("ABCDEF018"));
+        assert!( Mod17Hex.is_valid("00000009"));
+        assert!(!Mod17Hex.is_valid("ABCDEFG19"));
+    }
+
+    #[test]
+    fn finds_adjacent_numbers_with_alternate_checksum() {
+        for adjacent in find_adjacent("79927398718", &Luhn) {
+            assert!(Luhn.is_valid(&adjacent));
+        }
+        assert_eq!(find_adjacent("011000016", &AbaRouting), vec!["011000015"]);
     }
 
 }
\ No newline at end of file
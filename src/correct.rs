@@ -0,0 +1,305 @@
+use std::str;
+use crate::{digit_segments, Checksum, Font};
+
+// An account number reached by flipping segments of the scanned digits
+#[derive(Debug, PartialEq)]
+pub struct Correction {
+    // Corrected account number
+    pub account_number: String,
+
+    // Total number of segments flipped to reach the account number
+    pub distance: u32,
+}
+
+// Find account numbers which pass the checksum and can be reached from the scanned segments by
+// flipping at most max_digit_distance segments of any digit.  The digits which were read may have
+// at most max_entry_distance segments flipped between them, while every illegible digit may be
+// read as any character within max_digit_distance.  Each digit may become any character of the
+// font, measured against its nearest glyph.  Corrections are ranked by distance, nearest first, and then by the
+// position and value of the digits which changed.  Distances are searched nearest first, and if
+// more than max_combinations account numbers would have to be tried, only the corrections at the
+// distances which were searched in full are returned.
+pub fn find_corrections(register: &[u8], font: &Font, max_digit_distance: u32, max_entry_distance: u32, max_combinations: usize, checksum: &dyn Checksum) -> Vec<Correction> {
+    let uncertain = vec![0; register.len()];
+    find_uncertain_corrections(register, &uncertain, font, max_digit_distance, max_entry_distance, max_combinations, checksum)
+}
+
+// Find corrections as find_corrections does where some of the scanned segments are uncertain.
+// Uncertain segments match either value so only the certain segments count towards the distance,
+// and a reading which needs no flips at all is also offered.
+pub(crate) fn find_uncertain_corrections(register: &[u8], uncertain: &[u8], font: &Font, max_digit_distance: u32, max_entry_distance: u32, max_combinations: usize, checksum: &dyn Checksum) -> Vec<Correction> {
+    // Candidate characters for each position along with the distance to each
+    let options : Vec<Vec<(u8, u32)>> = register
+        .iter()
+        .zip(uncertain)
+        .map(|(segments, uncertain)| close_characters(*segments, *uncertain, font, max_digit_distance))
+        .collect();
+
+    // A digit is illegible if it reads as no character, or some of its segments are uncertain
+    let illegible : Vec<bool> = options
+        .iter()
+        .zip(uncertain)
+        .map(|(options, uncertain)| *uncertain != 0 || options.iter().all(|(_, distance)| *distance > 0))
+        .collect();
+
+    let mut search = Search {
+        options: &options,
+        illegible: &illegible,
+        checksum,
+        buffer: vec![0; register.len()],
+        found: Vec::new(),
+        remaining_combinations: max_combinations,
+        abandoned: false,
+        flips_required: uncertain.iter().all(|uncertain| *uncertain == 0),
+    };
+
+    // No correction can be further than the furthest option at every illegible digit along with
+    // the flips allowed for the rest
+    let furthest = |illegible_only: bool| -> u32 {
+        options
+            .iter()
+            .zip(&illegible)
+            .filter(|(_, illegible)| **illegible || !illegible_only)
+            .map(|(options, _)| options.iter().map(|(_, distance)| *distance).max().unwrap_or(0))
+            .sum()
+    };
+    let max_distance = furthest(false).min(furthest(true).saturating_add(max_entry_distance));
+    let mut found = Vec::new();
+    for distance in 0..=max_distance {
+        search.visit(0, distance, max_entry_distance, 0);
+        if search.abandoned {
+            break;
+        }
+        found.append(&mut search.found);
+    }
+
+    found.sort_by(|a, b| (a.distance, &a.changes).cmp(&(b.distance, &b.changes)));
+    found
+        .into_iter()
+        .map(|candidate| Correction { account_number: candidate.account_number, distance: candidate.distance })
+        .collect()
+}
+
+// Total number of segments which differ between the scanned segments and an account number
+pub fn segment_distance(register: &[u8], account_number: &str) -> u32 {
+    register
+        .iter()
+        .zip(account_number.bytes())
+        .map(|(segments, digit)| (digit_segments(digit).unwrap_or(0) ^ segments).count_ones())
+        .sum()
+}
+
+// Find the characters of the font within max_distance segments of the scanned segments, along
+// with the distance to the nearest glyph of each.  Uncertain segments are not counted.
+fn close_characters(segments: u8, uncertain: u8, font: &Font, max_distance: u32) -> Vec<(u8, u32)> {
+    let mut close = Vec::new();
+    for (glyph, ch) in font.glyphs() {
+        let distance = ((glyph ^ segments) & !uncertain).count_ones();
+        match close.iter_mut().find(|(close_ch, _)| *close_ch == ch) {
+            Some((_, close_distance)) => *close_distance = distance.min(*close_distance),
+            None => close.push((ch, distance)),
+        }
+    }
+    close.retain(|(_, distance)| *distance <= max_distance);
+    close
+}
+
+// A correction found by the search along with the digits which were changed
+struct Candidate {
+    account_number: String,
+    distance: u32,
+    changes: Vec<(usize, u8)>,
+}
+
+// State of a depth first search over the candidate digits for each position
+struct Search<'a> {
+    options: &'a [Vec<(u8, u32)>],
+    illegible: &'a [bool],
+    checksum: &'a dyn Checksum,
+    buffer: Vec<u8>,
+    found: Vec<Candidate>,
+
+    // Number of account numbers which may still be tried, and whether the search gave up after
+    // trying them all
+    remaining_combinations: usize,
+    abandoned: bool,
+
+    // Set when every segment is certain, so the reading with no flips is the scanned one
+    flips_required: bool,
+}
+
+impl Search<'_> {
+    // Try every candidate digit at the position which fits in the remaining distance, and in the
+    // flips left for the legible digits if it is one.  Only account numbers which use up all of
+    // the remaining distance are checked.
+    fn visit(&mut self, index: usize, remaining: u32, legible_remaining: u32, distance: u32) {
+        if self.abandoned {
+            return;
+        }
+        if index == self.buffer.len() {
+            if remaining == 0 {
+                self.check(distance);
+            }
+            return;
+        }
+
+        let illegible = self.illegible[index];
+        for &(digit, digit_distance) in &self.options[index] {
+            if digit_distance <= remaining && (illegible || digit_distance <= legible_remaining) {
+                let legible_remaining = if illegible { legible_remaining } else { legible_remaining - digit_distance };
+                self.buffer[index] = digit;
+                self.visit(index + 1, remaining - digit_distance, legible_remaining, distance + digit_distance);
+            }
+        }
+    }
+
+    // Record the account number in the buffer if it is a correction which passes the checksum
+    fn check(&mut self, distance: u32) {
+        if distance == 0 && self.flips_required {
+            return;
+        }
+        if self.remaining_combinations == 0 {
+            self.abandoned = true;
+            return;
+        }
+        self.remaining_combinations -= 1;
+
+        let account_number = str::from_utf8(&self.buffer).unwrap();
+        if self.checksum.is_valid(account_number) {
+            let changes = self.buffer
+                .iter()
+                .enumerate()
+                .filter(|(index, digit)| self.options[*index].iter().any(|option| option.0 == **digit && option.1 > 0))
+                .map(|(index, digit)| (index, *digit))
+                .collect();
+            self.found.push(Candidate { account_number: account_number.to_string(), distance, changes });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mod11;
+
+    fn register(account_number: &str) -> Vec<u8> {
+        account_number.bytes().filter_map(digit_segments).collect()
+    }
+
+    fn corrections(account_number: &str, max_digit_distance: u32, max_entry_distance: u32) -> Vec<(String, u32)> {
+        find_corrections(&register(account_number), &Font::decimal(), max_digit_distance, max_entry_distance, 1000, &Mod11)
+            .into_iter()
+            .map(|correction| (correction.account_number, correction.distance))
+            .collect()
+    }
+
+    #[test]
+    fn single_segment_corrections() {
+        assert_eq!(corrections("490067715", 1, 1), vec![
+            ("490867715".to_string(), 1),
+            ("490067115".to_string(), 1),
+            ("490067719".to_string(), 1),
+        ]);
+        assert_eq!(corrections("123456789", 0, 5), vec![]);
+    }
+
+    #[test]
+    fn multi_segment_corrections_are_ranked_by_distance() {
+        let found = corrections("498061715", 2, 2);
+        assert!(!found.is_empty());
+        assert!(found.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        for (account_number, distance) in &found {
+            assert!(Mod11.is_valid(account_number));
+            assert_eq!(segment_distance(&register("498061715"), account_number), *distance);
+        }
+        assert!(found.iter().any(|(_, distance)| *distance == 2));
+
+        // Two digits smudged at once
+        assert!(corrections("723456788", 1, 2).contains(&("123456789".to_string(), 2)));
+        assert!(!corrections("723456788", 1, 1).contains(&("123456789".to_string(), 2)));
+    }
+
+    #[test]
+    fn illegible_digits_must_change() {
+        let mut segments = register("490067115");
+        segments[5] = 0b01001100; // 7 with the top segment missing and an extra segment
+        let found = find_corrections(&segments, &Font::decimal(), 2, 2, 1000, &Mod11);
+        assert!(found.contains(&Correction { account_number: "490067115".to_string(), distance: 2 }));
+    }
+
+    #[test]
+    fn corrections_use_the_glyphs_of_the_font() {
+        // 7 with an upper left stroke is two segments from 1 but reads as 7 with variants
+        let mut segments = register("723456789");
+        segments[0] = 0b01001011;
+        assert_eq!(find_corrections(&segments, &Font::decimal(), 1, 1, 1000, &Mod11), vec![]);
+        assert_eq!(find_corrections(&segments, &Font::decimal(), 2, 2, 1000, &Mod11)[0],
+            Correction { account_number: "123456789".to_string(), distance: 2 });
+        assert_eq!(find_corrections(&segments, &Font::decimal().with_variants(), 2, 2, 1000, &Mod11)[0],
+            Correction { account_number: "123456789".to_string(), distance: 2 });
+        assert_eq!(find_corrections(&register("723456789"), &Font::decimal().with_variants(), 1, 1, 1000, &Mod11)[0],
+            Correction { account_number: "123456789".to_string(), distance: 1 });
+    }
+
+    #[test]
+    fn search_is_abandoned_after_max_combinations() {
+        assert_eq!(find_corrections(&register("490067715"), &Font::decimal(), 1, 1, 1000, &Mod11).len(), 3);
+        assert_eq!(find_corrections(&register("490067715"), &Font::decimal(), 1, 1, 2, &Mod11), vec![]);
+
+        // The corrections at the distances searched before the limit was reached are kept
+        let found = find_corrections(&register("490067715"), &Font::decimal(), 4, 4, 1000, &Mod11);
+        let nearest : Vec<&str> = found.iter().take(3).map(|correction| correction.account_number.as_str()).collect();
+        assert_eq!(nearest, ["490867715", "490067115", "490067719"]);
+        assert!(found.iter().any(|correction| correction.distance == 3));
+        assert!(found.iter().all(|correction| correction.distance < 4));
+    }
+
+    // Digits one segment from the scanned segments, ordered by the segment which differs as the
+    // original close match search returned them
+    fn find_register_digit_close_matches(segments: u8) -> Vec<u8> {
+        let mut close = close_characters(segments, 0, &Font::decimal(), 1);
+        close.retain(|(_, distance)| *distance == 1);
+        close.sort_by_key(|(ch, _)| (Font::decimal().segments(*ch).unwrap() ^ segments).trailing_zeros());
+        close.into_iter().map(|(ch, _)| ch).collect()
+    }
+
+    #[test]
+    fn possible_alternative_digits() {
+        assert_eq!(find_register_digit_close_matches(0b00100110), vec![]);
+        assert_eq!(find_register_digit_close_matches(0b01111010), vec!['0' as u8]);
+        assert_eq!(find_register_digit_close_matches(0b01000000), vec!['1' as u8]);
+        assert_eq!(find_register_digit_close_matches(0b00111100), vec!['2' as u8]);
+        assert_eq!(find_register_digit_close_matches(0b01101100), vec!['3' as u8]);
+        assert_eq!(find_register_digit_close_matches(0b01000110), vec!['4' as u8]);
+        assert_eq!(find_register_digit_close_matches(0b01100110), vec!['5' as u8]);
+        assert_eq!(find_register_digit_close_matches(0b01110110), vec!['6' as u8]);
+        assert_eq!(find_register_digit_close_matches(0b01001000), vec!['7' as u8]);
+        assert_eq!(find_register_digit_close_matches(0b01111110), vec!['8' as u8]);
+        assert_eq!(find_register_digit_close_matches(0b01101101), vec!['9' as u8]);
+        assert_eq!(find_register_digit_close_matches(0b01001010), vec!['1' as u8, '4' as u8]);
+        assert_eq!(find_register_digit_close_matches(0b01111101), vec!['8' as u8, '3' as u8, '2' as u8]);
+    }
+
+    #[test]
+    fn close_characters_within_distance() {
+        let close = |segments, max_distance| -> Vec<u8> {
+            let mut close : Vec<u8> = close_characters(segments, 0, &Font::decimal(), max_distance)
+                .into_iter()
+                .filter(|(_, distance)| *distance > 0)
+                .map(|(ch, _)| ch)
+                .collect();
+            close.sort();
+            close
+        };
+        assert_eq!(close(0b01111101, 0), vec![]);
+        assert_eq!(close(0b00100110, 2), vec![b'5']);
+        assert_eq!(close(0b01111101, 2), vec![b'0', b'2', b'3', b'6', b'8', b'9']);
+    }
+
+    #[test]
+    fn measures_segment_distance() {
+        assert_eq!(segment_distance(&register("123"), "123"), 0);
+        assert_eq!(segment_distance(&register("123"), "723"), 1);
+        assert_eq!(segment_distance(&register("888"), "000"), 3);
+    }
+}
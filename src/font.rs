@@ -0,0 +1,301 @@
+use std::fmt;
+use crate::{bit_pos, expected_chars, on_char, DIGIT_SEGMENTS, GLYPH_ROWS};
+
+// Segments of the letters A to F as drawn on a 7-segment display: A, b, C, d, E, F
+const HEX_LETTER_SEGMENTS : [u8; 6] = [
+    0b01011111, // A
+    0b01110110, // b
+    0b00110011, // C
+    0b01111100, // d
+    0b00110111, // E
+    0b00010111, // F
+];
+
+// Variants of the digits drawn by some 7-segment displays: 6 without its top bar, 7 with an upper
+// left stroke and 9 without its bottom bar
+const VARIANT_SEGMENTS : [(u8, u8); 3] = [
+    (0b01110110, b'6'),
+    (0b01001011, b'7'),
+    (0b01001111, b'9'),
+];
+
+// Set of glyphs the parser recognizes and the character each one stands for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    // Segments which are "on" for each glyph along with its character
+    glyphs: Vec<(u8, u8)>,
+}
+
+impl Font {
+    // The digits '0' through '9'
+    pub fn decimal() -> Font {
+        Font {
+            glyphs: DIGIT_SEGMENTS.iter().zip(b'0'..=b'9').map(|(segments, ch)| (*segments, ch)).collect(),
+        }
+    }
+
+    // The digits '0' through '9' followed by the letters 'A' through 'F'
+    pub fn hexadecimal() -> Font {
+        let mut font = Font::decimal();
+        font.glyphs.extend(HEX_LETTER_SEGMENTS.iter().zip(b'A'..=b'F').map(|(segments, ch)| (*segments, ch)));
+        font
+    }
+
+    // Read a font definition.  Each glyph is a header naming its character, such as [7], followed by
+    // three lines drawing it on the 3x3 grid used by entries.  Blank lines and lines starting with #
+    // may appear between glyphs.  A character may be drawn more than once; the first drawing is its
+    // canonical form.
+    pub fn parse(text: &str) -> Result<Font, FontError> {
+        let mut glyphs : Vec<(u8, u8)> = Vec::new();
+        let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line.trim_end()));
+        while let Some((line_number, line)) = lines.next() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |kind| FontError { kind, line_number };
+            let name = line
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+                .ok_or_else(|| error(FontErrorKind::ExpectedHeader))?;
+            let ch = match name.as_bytes() {
+                [ch] if ch.is_ascii_graphic() && *ch != b'?' => *ch,
+                _ => return Err(error(FontErrorKind::InvalidCharacter { name: name.to_string() })),
+            };
+
+            let mut segments = 0;
+            for row in 0..GLYPH_ROWS {
+                let Some((line_number, line)) = lines.next() else {
+                    return Err(error(FontErrorKind::TruncatedGlyph { rows: row }));
+                };
+                segments |= read_glyph_row(line, row).map_err(|kind| FontError { kind, line_number })?;
+            }
+
+            match glyphs.iter().find(|glyph| glyph.0 == segments) {
+                Some(&(_, existing)) if existing != ch => {
+                    return Err(error(FontErrorKind::DuplicateGlyph { ch: ch as char, existing: existing as char }));
+                }
+                Some(_) => {}
+                None => glyphs.push((segments, ch)),
+            }
+        }
+
+        if glyphs.is_empty() {
+            return Err(FontError { kind: FontErrorKind::NoGlyphs, line_number: 0 });
+        }
+        Ok(Font { glyphs })
+    }
+
+    // Look up a font by name.  None if there is no font with that name.
+    pub fn by_name(name: &str) -> Option<Font> {
+        match name {
+            "decimal" => Some(Font::decimal()),
+            "hex" => Some(Font::hexadecimal()),
+            _ => None,
+        }
+    }
+
+    // Determine the character drawn by the segments.  None if no glyph matches.
+    pub fn read(&self, segments: u8) -> Option<u8> {
+        self.glyphs.iter().find(|glyph| glyph.0 == segments).map(|glyph| glyph.1)
+    }
+
+    // Determine the segments which draw a character.  None if the font has no glyph for it.
+    pub fn segments(&self, ch: u8) -> Option<u8> {
+        self.glyphs.iter().find(|glyph| glyph.1 == ch).map(|glyph| glyph.0)
+    }
+
+    // Test if the segments are the canonical glyph of their character, the first one defined for
+    // it.  Segments which do not match a glyph are not canonical.
+    pub fn is_canonical(&self, segments: u8) -> bool {
+        self.read(segments).and_then(|ch| self.segments(ch)) == Some(segments)
+    }
+
+    // Add the variants of 6, 7 and 9 for the characters the font defines.  A variant whose drawing
+    // already stands for another character, such as b in the hexadecimal font, is left out.
+    pub fn with_variants(mut self) -> Font {
+        for (segments, ch) in VARIANT_SEGMENTS {
+            if self.segments(ch).is_some() && self.read(segments).is_none() {
+                self.glyphs.push((segments, ch));
+            }
+        }
+        self
+    }
+
+    // Segments and character of each glyph in the order they were defined
+    pub fn glyphs(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        self.glyphs.iter().copied()
+    }
+}
+
+// Read the segments drawn by one row of a glyph in a font definition
+fn read_glyph_row(line: &str, row: usize) -> Result<u8, FontErrorKind> {
+    let mut segments = 0;
+    for (col, ch) in line.chars().enumerate() {
+        let on = if col < 3 { on_char(row, col) } else { '\0' };
+        if ch == ' ' {
+            continue;
+        }
+        if ch != on {
+            return Err(FontErrorKind::UnexpectedChar { found: ch, expected: expected_chars(on), col: col + 1 });
+        }
+        segments |= 1 << bit_pos(row, col);
+    }
+    Ok(segments)
+}
+
+// Error found in a font definition
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontError {
+    // Nature of the error
+    pub kind: FontErrorKind,
+
+    // Line of the definition where the error occurred, 0 if it applies to the whole definition
+    pub line_number: usize,
+}
+
+// Kinds of error in a font definition
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontErrorKind {
+    // A line between glyphs is not a [c] header, a comment or blank
+    ExpectedHeader,
+
+    // A header names something other than a single printable ASCII character
+    InvalidCharacter {
+        // Text between the brackets
+        name: String,
+    },
+
+    // A drawing contains a character which cannot appear at its position
+    UnexpectedChar {
+        // Character found in the drawing
+        found: char,
+
+        // Characters allowed at the position
+        expected: Vec<char>,
+
+        // Column of the character, from 1
+        col: usize,
+    },
+
+    // The definition ended part way through the rows of a glyph
+    TruncatedGlyph {
+        // Number of rows read
+        rows: usize,
+    },
+
+    // The same drawing was given for two different characters
+    DuplicateGlyph {
+        // Character of the second drawing
+        ch: char,
+
+        // Character the drawing already stands for
+        existing: char,
+    },
+
+    // The definition contains no glyphs
+    NoGlyphs,
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line_number > 0 {
+            write!(f, "line {}: ", self.line_number)?;
+        }
+        match &self.kind {
+            FontErrorKind::ExpectedHeader => write!(f, "Expected a glyph header such as [0]."),
+            FontErrorKind::InvalidCharacter { name } =>
+                write!(f, "Glyph header [{}] must name a single printable ASCII character other than '?'.", name),
+            FontErrorKind::UnexpectedChar { found, expected, col } => {
+                let expected : Vec<String> = expected
+                    .iter()
+                    .map(|ch| if *ch == ' ' { "space".to_string() } else { format!("'{}'", ch) })
+                    .collect();
+                write!(f, "column {}: Expected {} but found '{}'.", col, expected.join(" or "), found)
+            }
+            FontErrorKind::TruncatedGlyph { rows } =>
+                write!(f, "Definition ended after {} of {} rows of a glyph.", rows, GLYPH_ROWS),
+            FontErrorKind::DuplicateGlyph { ch, existing } =>
+                write!(f, "Glyph for '{}' is already drawn as '{}'.", ch, existing),
+            FontErrorKind::NoGlyphs => write!(f, "Font defines no glyphs."),
+        }
+    }
+}
+
+impl Default for Font {
+    fn default() -> Font {
+        Font::decimal()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimal_glyphs() {
+        let font = Font::decimal();
+        assert_eq!(font.read(0b01001000), Some(b'1'));
+        assert_eq!(font.read(0b01011111), None);
+        assert_eq!(font.segments(b'8'), Some(0b01111111));
+        assert_eq!(font.segments(b'A'), None);
+        assert_eq!(font.glyphs().count(), 10);
+    }
+
+    #[test]
+    fn hexadecimal_glyphs() {
+        let font = Font::hexadecimal();
+        assert_eq!(font.read(0b01001000), Some(b'1'));
+        assert_eq!(font.read(0b01011111), Some(b'A'));
+        assert_eq!(font.read(0b01110110), Some(b'B'));
+        assert_eq!(font.segments(b'F'), Some(0b00010111));
+        assert_eq!(font.glyphs().count(), 16);
+
+        // Every glyph must be distinct
+        for (segments, ch) in font.glyphs() {
+            assert_eq!(font.read(segments), Some(ch));
+        }
+    }
+
+    #[test]
+    fn variant_glyphs() {
+        let font = Font::decimal().with_variants();
+        assert_eq!(font.read(0b01110110), Some(b'6'));
+        assert_eq!(font.read(0b01001011), Some(b'7'));
+        assert_eq!(font.read(0b01001111), Some(b'9'));
+        assert_eq!(font.segments(b'7'), Some(0b01001001));
+        assert!(font.is_canonical(0b01001001));
+        assert!(!font.is_canonical(0b01001011));
+        assert!(!font.is_canonical(0b01011111));
+
+        // The drawing of 6 without its top bar is already b
+        let font = Font::hexadecimal().with_variants();
+        assert_eq!(font.read(0b01110110), Some(b'B'));
+        assert_eq!(font.read(0b01001011), Some(b'7'));
+        assert_eq!(font.glyphs().count(), 18);
+    }
+
+    #[test]
+    fn parses_a_definition() {
+        assert_eq!(Font::parse(include_str!("../fonts/kata.font")), Ok(Font::decimal()));
+
+        // Variant drawings of the same character
+        let font = Font::parse("[7]\n _\n  |\n  |\n[7]\n _\n| |\n  |\n[L]\n\n|\n|_\n").unwrap();
+        assert_eq!(font.read(0b01001001), Some(b'7'));
+        assert_eq!(font.read(0b01001011), Some(b'7'));
+        assert_eq!(font.read(0b00110010), Some(b'L'));
+        assert_eq!(font.segments(b'7'), Some(0b01001001));
+    }
+
+    #[test]
+    fn reports_errors_in_a_definition() {
+        let error = |text| Font::parse(text).unwrap_err().to_string();
+        assert_eq!(error("7\n _\n  |\n  |\n"), "line 1: Expected a glyph header such as [0].");
+        assert_eq!(error("\n[10]\n"), "line 2: Glyph header [10] must name a single printable ASCII character other than '?'.");
+        assert_eq!(error("[7]\n _\n  |\n  /\n"), "line 4: column 3: Expected space or '|' but found '/'.");
+        assert_eq!(error("[7]\n _ |\n"), "line 2: column 4: Expected space but found '|'.");
+        assert_eq!(error("[7]\n _\n  |\n"), "line 1: Definition ended after 2 of 3 rows of a glyph.");
+        assert_eq!(error("[1]\n\n  |\n  |\n[l]\n\n  |\n  |\n"), "line 5: Glyph for 'l' is already drawn as '1'.");
+        assert_eq!(error("# Nothing here\n"), "Font defines no glyphs.");
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{digit_segments, render_segments, Checksum, Evaluator, Parser, ParserConfig, Result};
+
+// Characters which the parser never accepts, used to simulate scanner garbage
+const ILLEGAL_CHARS : &[u8] = b"xX#*@";
+
+// Settings for generating a synthetic test corpus
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    // Number of entries to generate
+    pub count: usize,
+
+    // Number of digits in each account number
+    pub digits: usize,
+
+    // Seed for the random number generator so a corpus can be reproduced
+    pub seed: u64,
+
+    // Fraction of entries whose account number fails the checksum
+    pub invalid_checksum_rate: f64,
+
+    // Fraction of entries with a single flipped segment
+    pub single_flip_rate: f64,
+
+    // Fraction of entries with several flipped segments
+    pub multi_flip_rate: f64,
+
+    // Fraction of entries containing an illegal character
+    pub illegal_char_rate: f64,
+}
+
+impl GeneratorConfig {
+    // Parser settings which give the expected results.  A flipped segment can leave a row blank or
+    // put a stroke where realignment would take the row for the wrong one, so entries are read
+    // without realignment, as --no-realign does.
+    pub fn parser_config(&self) -> ParserConfig {
+        ParserConfig { digits: self.digits, realign: false, ..ParserConfig::default() }
+    }
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> GeneratorConfig {
+        GeneratorConfig {
+            count: 100,
+            digits: 9,
+            seed: 1,
+            invalid_checksum_rate: 0.2,
+            single_flip_rate: 0.2,
+            multi_flip_rate: 0.1,
+            illegal_char_rate: 0.05,
+        }
+    }
+}
+
+// A generated entry and the result the processor is expected to produce for it
+pub struct GeneratedEntry {
+    // Lines of input for the entry, including the blank separator line
+    pub lines: [String; 4],
+
+    // Expected result of processing the entry
+    pub expected: Result,
+}
+
+// Generates random entries for testing the processor
+pub struct Generator<'a> {
+    // Generator settings
+    config: GeneratorConfig,
+
+    // Algorithm used to pick valid and invalid account numbers
+    checksum: &'a dyn Checksum,
+
+    // Parser which reads the rendered lines to find the status each entry is expected to have
+    parser: Parser,
+
+    // Converts the parsed status into the expected result
+    evaluator: Evaluator<'a>,
+
+    // Source of randomness
+    random: Random,
+
+    // Number of entries generated so far
+    index: usize,
+}
+
+impl<'a> Generator<'a> {
+    // Create a generator.  Account numbers need at least one digit, or no entry with an invalid
+    // checksum or a flipped segment could be built.
+    pub fn new(config: GeneratorConfig, checksum: &'a dyn Checksum) -> Generator<'a> {
+        assert!(config.digits > 0, "digits must be at least 1.");
+        let parser_config = config.parser_config();
+        Generator {
+            random: Random::new(config.seed),
+            evaluator: Evaluator::new(checksum, &parser_config),
+            parser: Parser::new(parser_config, 0),
+            config,
+            checksum,
+            index: 0,
+        }
+    }
+
+    // Pick a random account number which either passes or fails the checksum
+    fn account_number(&mut self, valid: bool) -> String {
+        loop {
+            let account_number : String = (0..self.config.digits)
+                .map(|_| (b'0' + self.random.below(10) as u8) as char)
+                .collect();
+            if self.checksum.is_valid(&account_number) == valid {
+                return account_number;
+            }
+        }
+    }
+
+    // Flip a random segment of a random digit
+    fn flip_segment(&mut self, register: &mut [u8]) {
+        let digit = self.random.below(register.len());
+        register[digit] ^= 1 << self.random.below(7);
+    }
+
+    // Replace a random cell of the glyphs with an illegal character
+    fn insert_illegal_char(&mut self, rows: &mut [String; 3]) {
+        let row = self.random.below(3);
+        let col = self.random.below(rows[row].len());
+        let ch = ILLEGAL_CHARS[self.random.below(ILLEGAL_CHARS.len())];
+        rows[row].replace_range(col..col + 1, &(ch as char).to_string());
+    }
+
+    // Parse the lines of an entry and evaluate the status they produce
+    fn parse(&mut self, lines: &[String; 4]) -> Option<Result> {
+        let mut expected = None;
+        for line in lines {
+            let status = self.parser.process_line(line);
+            let line_number = self.parser.get_line_number();
+            let pending = std::iter::from_fn(|| self.parser.next_pending());
+            for (status, line_number) in std::iter::once((status, line_number)).chain(pending) {
+                expected = expected.or_else(|| self.evaluator.evaluate(status, line_number, 0));
+            }
+        }
+        expected
+    }
+}
+
+impl Iterator for Generator<'_> {
+    type Item = GeneratedEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.config.count {
+            return None;
+        }
+        self.index += 1;
+
+        let valid = !self.random.chance(self.config.invalid_checksum_rate);
+        let account_number = self.account_number(valid);
+        let mut register : Vec<u8> = account_number.bytes().filter_map(digit_segments).collect();
+
+        let single_flip = self.config.single_flip_rate;
+        let multi_flip = single_flip + self.config.multi_flip_rate;
+        let illegal_char = multi_flip + self.config.illegal_char_rate;
+        let corruption = self.random.fraction();
+
+        if corruption < single_flip {
+            self.flip_segment(&mut register);
+        } else if corruption < multi_flip {
+            for _ in 0..2 + self.random.below(2) {
+                self.flip_segment(&mut register);
+            }
+        }
+
+        // An entry without a single segment reads as an empty slot, which is not reported
+        while register.iter().all(|segments| *segments == 0) {
+            self.flip_segment(&mut register);
+        }
+
+        let mut rows = render_segments(&register);
+        if corruption >= multi_flip && corruption < illegal_char {
+            self.insert_illegal_char(&mut rows);
+        }
+
+        let [top, middle, bottom] = rows;
+        let lines = [top, middle, bottom, String::new()];
+        let expected = self.parse(&lines)?;
+        Some(GeneratedEntry { lines, expected })
+    }
+}
+
+// Small xorshift random number generator so that corpora can be built without external crates
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        // The state must never be zero
+        Random { state: seed ^ 0x9e37_79b9_7f4a_7c15 | 1 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // Random number in the range 0..bound
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    // Random number in the range 0.0..1.0
+    fn fraction(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Returns true with the given probability
+    fn chance(&mut self, rate: f64) -> bool {
+        self.fraction() < rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{format_jsonl, Mod11, Processor};
+
+    fn generate(config: GeneratorConfig) -> (Vec<String>, Vec<String>) {
+        let mut lines = Vec::new();
+        let mut expected = Vec::new();
+        for entry in Generator::new(config, &Mod11) {
+            lines.extend(entry.lines);
+            expected.push(format_jsonl(entry.expected));
+        }
+        (lines, expected)
+    }
+
+    #[test]
+    fn processor_matches_expected_results() {
+        let config = GeneratorConfig {
+            count: 2000,
+            seed: 42,
+            invalid_checksum_rate: 0.3,
+            single_flip_rate: 0.3,
+            multi_flip_rate: 0.2,
+            illegal_char_rate: 0.1,
+            ..GeneratorConfig::default()
+        };
+        for digits in [1, 2, 9] {
+            let config = GeneratorConfig { digits, ..config.clone() };
+            let (lines, expected) = generate(config.clone());
+            assert_eq!(expected.len(), 2000);
+
+            let actual : Vec<String> = Processor::with_config(lines.into_iter(), &Mod11, config.parser_config()).map(format_jsonl).collect();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn uncorrupted_entries_follow_checksum_rate() {
+        let config = GeneratorConfig {
+            count: 50,
+            invalid_checksum_rate: 0.0,
+            single_flip_rate: 0.0,
+            multi_flip_rate: 0.0,
+            illegal_char_rate: 0.0,
+            ..GeneratorConfig::default()
+        };
+        for entry in Generator::new(config.clone(), &Mod11) {
+            assert!(matches!(entry.expected, Result::Success {..}));
+        }
+
+        let config = GeneratorConfig { invalid_checksum_rate: 1.0, ..config };
+        for entry in Generator::new(config, &Mod11) {
+            assert!(matches!(entry.expected, Result::BadChecksum {..}));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "digits must be at least 1.")]
+    fn account_numbers_need_digits() {
+        Generator::new(GeneratorConfig { digits: 0, ..GeneratorConfig::default() }, &Mod11);
+    }
+
+    #[test]
+    fn same_seed_gives_same_corpus() {
+        let config = GeneratorConfig { seed: 7, ..GeneratorConfig::default() };
+        assert_eq!(generate(config.clone()), generate(config.clone()));
+        assert_ne!(generate(config.clone()), generate(GeneratorConfig { seed: 8, ..config }));
+    }
+}
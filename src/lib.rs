@@ -1,31 +1,57 @@
 mod parse;
 mod checksum;
 mod process;
+mod correct;
+mod report;
+mod render;
+mod generate;
+mod normalize;
+mod parallel;
+mod font;
 
 use parse::*;
+pub use parse::{ErrorKind, LeftMargin, ParseError, ParserConfig, Scale, WarningKind};
+pub use checksum::{checksum_by_name, find_adjacent, AbaRouting, Checksum, Damm, Luhn, Mod11, Mod17Hex, Mod97, Verhoeff};
+pub use correct::{find_corrections, segment_distance, Correction};
+use correct::find_uncertain_corrections;
 use checksum::*;
 pub use process::*;
+pub use report::{format_csv, format_jsonl, Summary, CSV_HEADER};
+pub use render::{render, render_scaled, render_segments};
+pub use generate::{GeneratedEntry, Generator, GeneratorConfig};
+pub use normalize::{normalize_line, Normalizer};
+pub use parallel::ParallelProcessor;
+pub use font::{Font, FontError, FontErrorKind};
 
 // Format a result from Process as output string
 pub fn format_line(line : Result) -> String {
     match line {
-        Result::Success {account_number, line_number: _} => account_number,
-        Result::BadChecksum {account_number, line_number, mut alternates} => {
+        Result::Success {account_number, ..} => account_number,
+        Result::BadChecksum {account_number, line_number, mut alternates, ..} => {
             match alternates.len() {
                 0 => format!("{} ERR [line {}]", account_number, line_number),
                 1 => alternates.pop().unwrap(), // Assume that if there is only 1 alternate it must be correct!
                 _ => format!("{} AMB [line {} could be {:?}]",account_number, line_number, alternates),
             }
         }
-        Result::BadDigits {account_number, line_number, mut alternates} => {
+        Result::BadDigits {account_number, line_number, mut alternates, ..} => {
             match alternates.len() {
                 0 => format!("{} ILL [line {}]", account_number, line_number),
                 1 => alternates.pop().unwrap(), // Assume that if there is only 1 alternate it must be correct!
                 _ => format!("{} AMB [line {} could be {:?}]",account_number, line_number, alternates),
             }
         },
-        Result::Error {message, line_number, col, row} =>
-            format!("ERROR: {}:{}: row {}: {}", line_number, col, row, message),
+        Result::Error {kind, line_number, col, row, ..} =>
+            format!("ERROR: {}:{}: row {}: {}", line_number, col, row, kind),
+        Result::Errors {account_number, errors, line_number, ..} => {
+            let mut lines = vec![format!("ERRORS: {} [line {}]", account_number, line_number)];
+            lines.extend(errors.iter().map(|error| {
+                format!("ERROR: {}:{}: row {}: {}", error.line_number, error.col, error.row, error.kind)
+            }));
+            lines.join("\n")
+        }
+        Result::Warning {kind, line_number, ..} =>
+            format!("WARNING: {}: {}", line_number, kind),
     }
 }
 
@@ -214,7 +240,7 @@ mod tests {
 
     fn processx(input: Vec<String>) -> Vec<String> {
         let iter = input.iter().map(|s| s.to_string());
-        let output: Vec<String> = Processor::new(iter).map(|line| format_line(line)).collect();
+        let output: Vec<String> = Processor::new(iter, &Mod11).map(|line| format_line(line)).collect();
         output
     }
 
@@ -1,60 +1,394 @@
 use std::env;
-use std::fs::File;
-use std::io::{self, prelude::*, BufReader};
-use bankocr::{format_line, Processor};
-
-fn main() -> io::Result<()> {
-    let args : Vec<String> = env::args().collect();
-    if args.len() == 3 {
-        process_file(&args[1], &args[2])?;
+use std::fs::{self, File};
+use std::io::{self, prelude::*, BufReader, BufWriter};
+use std::process::ExitCode;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use bankocr::{checksum_by_name, format_csv, format_jsonl, format_line, render, Checksum, Font, Generator, GeneratorConfig, LeftMargin, Mod11, ParallelProcessor, ParserConfig, Processor, Scale, Summary, CSV_HEADER};
+
+// Exit codes
+const EXIT_ALL_VALID : u8 = 0;     // Every entry was read and passed the checksum
+const EXIT_NEEDS_REVIEW : u8 = 1;  // Some entries were illegible, failed the checksum or were misaligned
+const EXIT_PARSE_ERRORS : u8 = 2;  // Some entries could not be parsed
+const EXIT_IO_FAILURE : u8 = 3;    // Reading or writing a file failed
+const EXIT_USAGE : u8 = 64;        // The command line arguments are invalid
+
+const USAGE : &str = "Usage: bank_ocr [--digits <count>] [--checksum <mod11|luhn|mod97|verhoeff|damm|aba|mod17hex>] [--format <text|jsonl|csv>]
+                [--max-digit-distance <segments>] [--max-entry-distance <segments>] [--max-combinations <count>]
+                [--left-margin <columns|auto>] [--scale <factor|auto>] [--fields <count>] [--field-gap <columns>]
+                [--tab-width <columns>] [--no-separators] [--no-realign] [--all-errors] [--lenient]
+                [--noise-map <char>=<stroke>[,...]] [--stroke-map <char>=<stroke>[,...]]
+                [--font <decimal|hex|file>] [--variants] [--threads <count>] [<input file> [<output file>]]
+       bank_ocr render <account number>...
+       bank_ocr generate [--count <entries>] [--seed <number>] [--digits <count>] [--checksum <name>]
+                         [--invalid-rate <fraction>] [--flip-rate <fraction>] [--multi-flip-rate <fraction>]
+                         [--illegal-rate <fraction>] <output file> <expected results file>
+
+Input and output default to stdin and stdout.  Use - to name either explicitly.
+--left-margin sets the number of columns before the glyphs, such as indentation or a line number
+gutter.  By default it is detected from the first entry.
+--scale reads glyphs printed at a larger size, such as 2 for digits 6 columns wide and 5 rows high.
+By default it is detected from the first entry.
+--fields reads several entries laid out side by side in each band of lines, separated by --field-gap
+columns (default 0).
+--tab-width sets the distance between tab stops when tabs in the input are expanded (default 8).
+Line numbers count a carriage return on its own as a line break, as most editors do.
+--no-separators reads entries which are not followed by a blank line.
+--no-realign stops the framing of entries from being reset when a line does not fit its row.
+--all-errors reports every error in an entry rather than stopping at the first.
+--lenient treats stray characters in a glyph as noise.  Characters named by --noise-map are read as
+the stroke they stand for, for example -=_,!=|.  Setting a noise map implies --lenient.
+--stroke-map adds characters which are always read as a stroke, in the same form as --noise-map.
+The box drawing characters │ and ¦ are read as | and ─ and ▁ as _ by default.
+--font selects the glyphs which are recognized.  hex adds the letters A, b, C, d, E and F.  Any other
+name is read as a font definition file; see fonts/kata.font for the format.  Only mod17hex accepts
+letters, so hexadecimal references need --checksum mod17hex to be read as valid.
+--variants also reads 6 without its top bar, 7 with an upper left stroke and 9 without its bottom bar,
+and marks the entry as non-canonical in jsonl and csv output.
+--threads sets the number of threads used to search for corrections (default one per CPU).  Entries
+are still read in order, so results are the same as with --threads 1.
+
+generate writes random entries along with the results expected for them in jsonl.  They are the
+results of reading the entries with the same --digits and --checksum and with --no-realign --format
+jsonl, as realignment may take a row spoilt by a flipped segment for a misplaced line.
+
+Exit status is 0 if every entry is valid, 1 if some entries need review, 2 if some entries could not
+be parsed, 3 if reading or writing failed and 64 if the arguments are invalid.";
+
+// Options given on the command line
+struct Options {
+    input: String,
+    output: String,
+    config: ParserConfig,
+    font_file: Option<String>,
+    checksum: &'static dyn Checksum,
+    format: fn(bankocr::Result) -> String,
+    header: Option<&'static str>,
+    threads: usize,
+}
+
+fn main() -> ExitCode {
+    let args : Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("render") if args.len() > 1 => render_numbers(&args[1..]).map(|_| EXIT_ALL_VALID),
+        Some("generate") => match parse_generate_args(&args[1..]) {
+            Some((config, checksum, output, expected)) =>
+                generate_file(config, checksum, &output, &expected).map(|_| EXIT_ALL_VALID),
+            None => Ok(EXIT_USAGE),
+        },
+        Some("render") => Ok(EXIT_USAGE),
+        _ => match parse_args(&args) {
+            Some(options) => process_file(&options).map(|summary| {
+                eprintln!("{}", summary);
+                exit_code(&summary)
+            }),
+            None => Ok(EXIT_USAGE),
+        },
+    };
+
+    match result {
+        Ok(EXIT_USAGE) => {
+            eprintln!("{}", USAGE);
+            ExitCode::from(EXIT_USAGE)
+        }
+        Ok(code) => ExitCode::from(code),
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::from(EXIT_IO_FAILURE)
+        }
+    }
+}
+
+// Choose the exit code which reflects the results of a run
+fn exit_code(summary: &Summary) -> u8 {
+    if summary.error > 0 {
+        EXIT_PARSE_ERRORS
+    } else if summary.needs_review() > 0 || summary.warning > 0 {
+        EXIT_NEEDS_REVIEW
     } else {
-        println!("Usage: bank_ocr <input file> <output file>");
+        EXIT_ALL_VALID
     }
-    Ok(())
 }
 
-fn process_file(input: &String, output: &String) -> io::Result<()> {
-    println!("Parsing {} into {}", input, output);
+// Parse the command line arguments.  Returns None if the arguments are invalid.
+fn parse_args(args: &[String]) -> Option<Options> {
+    let mut config = ParserConfig { left_margin: LeftMargin::Auto, scale: Scale::Auto, ..ParserConfig::default() };
+    let mut checksum : &'static dyn Checksum = &Mod11;
+    let mut format : fn(bankocr::Result) -> String = format_line;
+    let mut header = None;
+    let mut font_file = None;
+    let mut threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let mut files = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--digits" => {
+                config.digits = iter.next()?.parse().ok().filter(|digits| *digits > 0)?;
+            }
+            "--checksum" => {
+                checksum = checksum_by_name(iter.next()?)?;
+            }
+            "--max-digit-distance" => {
+                config.max_digit_distance = iter.next()?.parse().ok()?;
+            }
+            "--max-entry-distance" => {
+                config.max_entry_distance = iter.next()?.parse().ok()?;
+            }
+            "--max-combinations" => {
+                config.max_combinations = iter.next()?.parse().ok()?;
+            }
+            "--left-margin" => {
+                config.left_margin = match iter.next()?.as_str() {
+                    "auto" => LeftMargin::Auto,
+                    columns => LeftMargin::Fixed(columns.parse().ok()?),
+                };
+            }
+            "--scale" => {
+                config.scale = match iter.next()?.as_str() {
+                    "auto" => Scale::Auto,
+                    factor => Scale::Fixed(factor.parse().ok().filter(|factor| *factor > 0)?),
+                };
+            }
+            "--fields" => {
+                config.fields = iter.next()?.parse().ok().filter(|fields| *fields > 0)?;
+            }
+            "--field-gap" => {
+                config.field_gap = iter.next()?.parse().ok()?;
+            }
+            "--tab-width" => {
+                config.tab_width = iter.next()?.parse().ok().filter(|width| *width > 0)?;
+            }
+            "--no-separators" => {
+                config.separators = false;
+            }
+            "--no-realign" => {
+                config.realign = false;
+            }
+            "--all-errors" => {
+                config.collect_errors = true;
+            }
+            "--lenient" => {
+                config.lenient = true;
+            }
+            "--noise-map" => {
+                config.noise_map = parse_stroke_map(iter.next()?)?;
+                config.lenient = true;
+            }
+            "--stroke-map" => {
+                config.stroke_map.extend(parse_stroke_map(iter.next()?)?);
+            }
+            "--font" => {
+                let name = iter.next()?;
+                match Font::by_name(name) {
+                    Some(font) => config.font = font,
+                    None => font_file = Some(name.clone()),
+                }
+            }
+            "--variants" => {
+                config.variants = true;
+            }
+            "--threads" => {
+                threads = iter.next()?.parse().ok().filter(|threads| *threads > 0)?;
+            }
+            "--format" => {
+                let name = iter.next()?;
+                format = match name.as_str() {
+                    "text" => format_line,
+                    "jsonl" => format_jsonl,
+                    "csv" => format_csv,
+                    _ => return None,
+                };
+                header = if name == "csv" { Some(CSV_HEADER) } else { None };
+            }
+            _ => files.push(arg.clone()),
+        }
+    }
+
+    if files.len() > 2 {
+        return None;
+    }
+    let mut files = files.into_iter();
+    let input = files.next().unwrap_or("-".to_string());
+    let output = files.next().unwrap_or("-".to_string());
+    Some(Options { input, output, config, font_file, checksum, format, header, threads })
+}
+
+// Parse the arguments of the generate command.  Returns None if the arguments are invalid.
+fn parse_generate_args(args: &[String]) -> Option<(GeneratorConfig, &'static dyn Checksum, String, String)> {
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |time| time.as_nanos() as u64);
+    let mut config = GeneratorConfig { seed, ..GeneratorConfig::default() };
+    let mut checksum : &'static dyn Checksum = &Mod11;
+    let mut files = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--count" => config.count = iter.next()?.parse().ok()?,
+            "--seed" => config.seed = iter.next()?.parse().ok()?,
+            "--digits" => config.digits = iter.next()?.parse().ok().filter(|digits| *digits > 0)?,
+            "--checksum" => checksum = checksum_by_name(iter.next()?)?,
+            "--invalid-rate" => config.invalid_checksum_rate = parse_rate(iter.next()?)?,
+            "--flip-rate" => config.single_flip_rate = parse_rate(iter.next()?)?,
+            "--multi-flip-rate" => config.multi_flip_rate = parse_rate(iter.next()?)?,
+            "--illegal-rate" => config.illegal_char_rate = parse_rate(iter.next()?)?,
+            _ => files.push(arg.clone()),
+        }
+    }
+
+    if files.len() != 2 {
+        return None;
+    }
+    let expected = files.pop()?;
+    let output = files.pop()?;
+    Some((config, checksum, output, expected))
+}
+
+// Parse a fraction in the range 0.0 to 1.0
+fn parse_rate(arg: &str) -> Option<f64> {
+    arg.parse().ok().filter(|rate| (0.0..=1.0).contains(rate))
+}
 
-    let reader = open_input(input)?;
-    let mut writer = open_output(output)?;
+// Write generated entries to the output file and their expected results to the expected file
+fn generate_file(config: GeneratorConfig, checksum: &dyn Checksum, output: &str, expected: &str) -> io::Result<()> {
+    eprintln!("Generating {} entries into {} with expected results in {} (seed {})", config.count, output, expected, config.seed);
 
-    Processor::new(
-        reader.lines().flat_map(|line| line)
-    ).map(format_line).for_each(|out_line| {
-        let result = writeln!(writer, "{}", out_line);
-        if let Err(error) = result {
-            panic!("Error writing to output: {}", error);
+    let mut output_writer = open_output(output)?;
+    let mut expected_writer = open_output(expected)?;
+    for entry in Generator::new(config, checksum) {
+        for line in entry.lines {
+            writeln!(output_writer, "{}", line)?;
         }
-    });
+        writeln!(expected_writer, "{}", format_jsonl(entry.expected))?;
+    }
+    output_writer.flush()?;
+    expected_writer.flush()
+}
+
+// Process the input file into the output file.  Returns a tally of the results.
+fn process_file(options: &Options) -> io::Result<Summary> {
+    let mut config = options.config.clone();
+    if let Some(file) = &options.font_file {
+        config.font = load_font(file)?;
+    }
 
+    eprintln!("Parsing {} into {}", display_name(&options.input, "stdin"), display_name(&options.output, "stdout"));
+
+    let reader = open_input(&options.input)?;
+    let mut writer = open_output(&options.output)?;
+    if let Some(header) = options.header {
+        writeln!(writer, "{}", header)?;
+    }
+
+    let mut summary = Summary::default();
+    // A line which is not valid UTF-8 is passed on with the invalid bytes replaced, so that the
+    // framing of entries and the line numbers are kept.  Reading stops at the first I/O error,
+    // which is reported once the lines before it have been processed.
+    let mut read_error = None;
+    let lines = reader
+        .split(b'\n')
+        .map_while(|line| line.map_err(|error| read_error = Some(error)).ok())
+        .map(|line| String::from_utf8_lossy(&line).into_owned());
+    let results : Box<dyn Iterator<Item = bankocr::Result>> = if options.threads > 1 {
+        Box::new(ParallelProcessor::new(lines, options.checksum, config, options.threads))
+    } else {
+        Box::new(Processor::with_config(lines, options.checksum, config))
+    };
+    for result in results {
+        summary.add(&result);
+        writeln!(writer, "{}", (options.format)(result))?;
+    }
+
+    writer.flush()?;
+    if let Some(error) = read_error {
+        return Err(error);
+    }
+    Ok(summary)
+}
+
+// Write the glyphs for each account number to stdout, one entry per number
+fn render_numbers(account_numbers: &[String]) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    for account_number in account_numbers {
+        for row in render(account_number) {
+            writeln!(stdout, "{}", row)?;
+        }
+        writeln!(stdout)?;
+    }
     Ok(())
 }
 
-fn open_input(input: &String) -> io::Result<BufReader<File>> {
+// Parse a map of characters to the strokes they stand for, of the form <char>=<stroke>,...  None if
+// it is malformed.
+fn parse_stroke_map(arg: &str) -> Option<Vec<(char, char)>> {
+    arg.split(',')
+        .map(|pair| {
+            let mut chars = pair.chars();
+            match (chars.next(), chars.next(), chars.next(), chars.next()) {
+                (Some(ch), Some('='), Some(stroke @ ('|' | '_')), None) => Some((ch, stroke)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// Read a font definition file.  An invalid definition is reported as invalid data.
+fn load_font(file: &str) -> io::Result<Font> {
+    let text = match fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(error) => {
+            eprintln!("Error opening font file {}.", file);
+            return io::Result::Err(error);
+        }
+    };
+    match Font::parse(&text) {
+        Ok(font) => io::Result::Ok(font),
+        Err(error) => {
+            eprintln!("Error in font file {}.", file);
+            return io::Result::Err(io::Error::new(io::ErrorKind::InvalidData, error.to_string()));
+        }
+    }
+}
+
+// Name of a file for messages, where - stands for a standard stream
+fn display_name<'a>(file: &'a str, stream: &'a str) -> &'a str {
+    if file == "-" { stream } else { file }
+}
+
+// Open the input file for reading, or stdin if the file is -
+fn open_input(input: &str) -> io::Result<Box<dyn BufRead>> {
+    if input == "-" {
+        return io::Result::Ok(Box::new(io::stdin().lock()));
+    }
+
     let input_result = File::open(input);
     match input_result {
         Result::Err(error) => {
-            println!("Error opening input file {}.", input);
+            eprintln!("Error opening input file {}.", input);
             return io::Result::Err(error);
         }
 
         Result::Ok(file) => {
-            return io::Result::Ok(BufReader::new(file))
+            return io::Result::Ok(Box::new(BufReader::new(file)))
         }
     }
 }
 
-fn open_output(output: &String) -> io::Result<File> {
+// Open the output file for writing, or stdout if the file is -
+fn open_output(output: &str) -> io::Result<Box<dyn Write>> {
+    if output == "-" {
+        return io::Result::Ok(Box::new(BufWriter::new(io::stdout().lock())));
+    }
+
     let output_result = File::create(output);
     match output_result {
         Result::Err(error) => {
-            println!("Error opening output file {}.", output);
+            eprintln!("Error opening output file {}.", output);
             return io::Result::Err(error);
         }
 
         Result::Ok(file) => {
-            return io::Result::Ok(file);
+            return io::Result::Ok(Box::new(BufWriter::new(file)));
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,104 @@
+use std::collections::VecDeque;
+
+// Cleans up lines of input before they reach the parser.  Line endings left by the reader are
+// removed, a carriage return on its own is treated as a line break, tabs are expanded to spaces
+// and trailing whitespace is stripped.  Line numbers count the lines after splitting, so they
+// match an editor which also breaks lines on a lone carriage return rather than a count of
+// newlines.
+pub struct Normalizer<I>
+    where I: Iterator<Item = String>
+{
+    // Iterator supplying raw input lines
+    lines: I,
+
+    // Number of columns between tab stops
+    tab_width: usize,
+
+    // Lines split from a raw line which have not been returned yet
+    pending: VecDeque<String>,
+}
+
+impl<I> Normalizer<I>
+    where I: Iterator<Item = String>
+{
+    pub fn new(lines: I, tab_width: usize) -> Normalizer<I> {
+        Normalizer {
+            lines,
+            tab_width,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<I> Iterator for Normalizer<I>
+    where I: Iterator<Item = String>
+{
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(line) = self.pending.pop_front() {
+            return Some(line);
+        }
+
+        let raw = self.lines.next()?;
+        let raw = raw.strip_suffix('\n').unwrap_or(&raw);
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        let mut lines = raw.split('\r').map(|line| normalize_line(line, self.tab_width));
+        let first = lines.next();
+        self.pending.extend(lines);
+        first
+    }
+}
+
+// Expand tabs to the next tab stop and strip trailing whitespace from a single line
+pub fn normalize_line(line: &str, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    let mut normalized = String::with_capacity(line.len());
+    let mut col = 0;
+    for ch in line.chars() {
+        if ch == '\t' {
+            let spaces = tab_width - col % tab_width;
+            normalized.extend(std::iter::repeat_n(' ', spaces));
+            col += spaces;
+        } else {
+            normalized.push(ch);
+            col += 1;
+        }
+    }
+    normalized.truncate(normalized.trim_end().len());
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(lines: &[&str], tab_width: usize) -> Vec<String> {
+        Normalizer::new(lines.iter().map(|line| line.to_string()), tab_width).collect()
+    }
+
+    #[test]
+    fn removes_line_endings() {
+        assert_eq!(normalize(&[" _ \r\n", "| |\r", "|_|\n", ""], 8), [" _", "| |", "|_|", ""]);
+    }
+
+    #[test]
+    fn splits_on_lone_carriage_returns() {
+        assert_eq!(normalize(&[" _ \r| |\r|_|\r\r"], 8), [" _", "| |", "|_|", ""]);
+    }
+
+    #[test]
+    fn expands_tabs_to_tab_stops() {
+        assert_eq!(normalize_line("\t_", 4), "    _");
+        assert_eq!(normalize_line(" _\t|", 4), " _  |");
+        assert_eq!(normalize_line("|_|\t_", 3), "|_|   _");
+        assert_eq!(normalize_line("\t|", 0), " |");
+        assert_eq!(normalize_line("\u{2502}\u{2500}\t\u{2502}", 4), "\u{2502}\u{2500}  \u{2502}");
+    }
+
+    #[test]
+    fn strips_trailing_whitespace() {
+        assert_eq!(normalize_line("  |  |   \t ", 8), "  |  |");
+        assert_eq!(normalize_line("   ", 8), "");
+    }
+}
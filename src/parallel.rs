@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+use std::thread;
+use crate::{Checksum, Entries, Evaluator, ParserConfig, Result, Status};
+
+// Number of entries handed to each thread at a time
+const CHUNK_ENTRIES : usize = 1000;
+
+// Processes input on several threads, returning the same results in the same order as Processor.
+// Lines are parsed in order on the calling thread, so realignment after a missing or extra line
+// works exactly as it does for Processor.  The parsed entries are then split into chunks and the
+// search for corrections, where most of the time goes, is run on a thread for each chunk.
+pub struct ParallelProcessor<'a, I>
+    where I: Iterator<Item = String>
+{
+    // Entries read from the input lines
+    entries: Entries<I>,
+
+    // Converts parsed entries into results
+    evaluator: Evaluator<'a>,
+
+    // Number of chunks processed at the same time
+    threads: usize,
+
+    // Number of entries in each chunk
+    chunk_entries: usize,
+
+    // Results of the last batch of chunks which have not been returned yet
+    results: VecDeque<Result>,
+}
+
+impl<'a, I> ParallelProcessor<'a, I>
+    where I: Iterator<Item = String>
+{
+    // Create a processor which runs on the given number of threads
+    pub fn new(lines: I, checksum: &'a dyn Checksum, config: ParserConfig, threads: usize) -> ParallelProcessor<'a, I> {
+        ParallelProcessor {
+            entries: Entries::new(lines, &config),
+            evaluator: Evaluator::new(checksum, &config),
+            threads: threads.max(1),
+            chunk_entries: CHUNK_ENTRIES,
+            results: VecDeque::new(),
+        }
+    }
+
+    // Parse a chunk of entries for each thread and evaluate the chunks in parallel.  False once
+    // the input has run out.
+    fn process_batch(&mut self) -> bool {
+        let wanted = self.chunk_entries.max(1) * self.threads;
+        let mut batch : Vec<(Status, usize, usize)> = self.entries.by_ref().take(wanted).collect();
+        if batch.is_empty() {
+            return false;
+        }
+
+        let evaluator = &self.evaluator;
+        let chunk_results : Vec<Vec<Result>> = thread::scope(|scope| {
+            let handles : Vec<_> = batch.chunks_mut(self.chunk_entries.max(1)).map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter_mut()
+                        .filter_map(|(status, line_number, field)| {
+                            let status = std::mem::replace(status, Status::Incomplete);
+                            evaluator.evaluate(status, *line_number, *field)
+                        })
+                        .collect()
+                })
+            }).collect();
+            handles.into_iter().map(|handle| handle.join().expect("processing thread panicked")).collect()
+        });
+        self.results.extend(chunk_results.into_iter().flatten());
+        true
+    }
+}
+
+impl<I> Iterator for ParallelProcessor<'_, I>
+    where I: Iterator<Item = String>
+{
+    type Item = Result;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(result) = self.results.pop_front() {
+                return Some(result);
+            }
+            if !self.process_batch() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{format_jsonl, Generator, GeneratorConfig, LeftMargin, Mod11, Processor};
+
+    fn generate(count: usize) -> Vec<String> {
+        let config = GeneratorConfig { count, ..GeneratorConfig::default() };
+        Generator::new(config, &Mod11).flat_map(|entry| entry.lines).collect()
+    }
+
+    // Compare the results of every thread count with those of a sequential Processor
+    fn assert_matches_sequential(input: Vec<String>, config: ParserConfig, chunk_entries: usize) {
+        let expected : Vec<String> = Processor::with_config(input.clone().into_iter(), &Mod11, config.clone()).map(format_jsonl).collect();
+        for threads in [1, 3, 8] {
+            let mut processor = ParallelProcessor::new(input.clone().into_iter(), &Mod11, config.clone(), threads);
+            processor.chunk_entries = chunk_entries;
+            let output : Vec<String> = processor.map(format_jsonl).collect();
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn matches_sequential_results() {
+        assert_matches_sequential(generate(50), ParserConfig::default(), 4);
+    }
+
+    #[test]
+    fn margin_is_detected_once() {
+        let input : Vec<String> = generate(6).into_iter().map(|line| format!("  {}", line)).collect();
+        let config = ParserConfig { left_margin: LeftMargin::Auto, ..ParserConfig::default() };
+        assert_matches_sequential(input, config, 1);
+    }
+
+    #[test]
+    fn misaligned_input_is_realigned_across_chunks() {
+        let mut input = generate(30);
+        input.remove(9);
+        input.insert(50, String::new());
+        assert_matches_sequential(input, ParserConfig::default(), 2);
+    }
+}
@@ -1,27 +1,200 @@
+use std::collections::VecDeque;
+use std::fmt;
 use std::str;
+use crate::Font;
 
 const ILLEGIBLE : u8 = '?' as u8;
 
+// Number of rows of glyphs in an entry, not counting the separator line
+pub const GLYPH_ROWS : usize = 3;
+
+// Settings which control how entries are parsed
+#[derive(Debug, Clone)]
+pub struct ParserConfig {
+    // Number of digits in each account number
+    pub digits: usize,
+
+    // Maximum number of segments which may be flipped in a single digit when looking for
+    // corrections
+    pub max_digit_distance: u32,
+
+    // Maximum number of segments which may be flipped across the digits of an entry which were
+    // read when looking for corrections.  Illegible digits are only limited by max_digit_distance.
+    pub max_entry_distance: u32,
+
+    // Maximum number of account numbers to try when searching for corrections.  Corrections are
+    // searched nearest first, and once the limit is reached only those at the distances already
+    // searched in full are offered.
+    pub max_combinations: usize,
+
+    // Number of columns between tab stops when tabs in the input are expanded
+    pub tab_width: usize,
+
+    // Columns before the glyphs which are ignored, such as indentation or a line number gutter
+    pub left_margin: LeftMargin,
+
+    // Size at which the glyphs are drawn.  At scale s each digit is 3s columns wide and 2s + 1
+    // rows high, with its strokes drawn as runs of s characters.
+    pub scale: Scale,
+
+    // Number of entries laid out side by side in each band of lines
+    pub fields: usize,
+
+    // Number of columns between the entries in a band
+    pub field_gap: usize,
+
+    // Each entry is followed by a blank separator line.  When false entries follow one another
+    // directly.
+    pub separators: bool,
+
+    // Keep reading an entry after an error so that every error in it is reported together
+    pub collect_errors: bool,
+
+    // Treat stray characters in a glyph as scanner noise rather than errors
+    pub lenient: bool,
+
+    // Characters which scanners print in place of a stroke, and the stroke each stands for.
+    // Only used in lenient mode.
+    pub noise_map: Vec<(char, char)>,
+
+    // Characters which stand for a stroke exactly, such as the box drawing characters printed by
+    // some scan converters.  They are read as the stroke in every mode.
+    pub stroke_map: Vec<(char, char)>,
+
+    // Reset the framing of entries when a line does not fit the row it falls on, so that a
+    // missing or extra line does not corrupt the rest of the input
+    pub realign: bool,
+
+    // Glyphs recognized in entries and the characters they stand for
+    pub font: Font,
+
+    // Also recognize the variants of 6, 7 and 9 drawn by some displays.  Results record when a
+    // variant was read.
+    pub variants: bool,
+}
+
+impl Default for ParserConfig {
+    fn default() -> ParserConfig {
+        ParserConfig {
+            digits: 9,
+            max_digit_distance: 1,
+            max_entry_distance: 1,
+            max_combinations: 1000,
+            tab_width: 8,
+            left_margin: LeftMargin::Fixed(0),
+            scale: Scale::Fixed(1),
+            fields: 1,
+            field_gap: 0,
+            separators: true,
+            collect_errors: false,
+            lenient: false,
+            noise_map: vec![('-', '_'), ('!', '|'), ('l', '|'), ('I', '|')],
+            stroke_map: vec![('\u{2502}', '|'), ('\u{00a6}', '|'), ('\u{2500}', '_'), ('\u{2581}', '_')],
+            realign: true,
+            font: Font::default(),
+            variants: false,
+        }
+    }
+}
+
+impl ParserConfig {
+    // The font glyphs are read with, including the variants when they are recognized.  Entries
+    // are corrected with the same font so that a variant costs no flips.
+    pub(crate) fn reading_font(&self) -> Font {
+        if self.variants { self.font.clone().with_variants() } else { self.font.clone() }
+    }
+}
+
+// Width of the margin to the left of the glyphs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LeftMargin {
+    // Detect the margin from the first entry.  The glyphs are assumed to be the only strokes on
+    // its rows.
+    Auto,
+
+    // Fixed number of columns
+    Fixed(usize),
+}
+
+// Size at which glyphs are drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scale {
+    // Detect the scale from the first entry.  Every stroke is assumed to be a run of the same
+    // length.
+    Auto,
+
+    // Fixed scale, 1 for the standard 3x3 glyphs
+    Fixed(usize),
+}
+
 // Parser of Bank OCR account numbers
 pub struct Parser {
-    // Register to hold the state of each segment of the 9 digits
+    // Register to hold the state of each segment of the digits
     // Each digit is imagined as a 7 segment LED display so the status of the 7 segments can
     // be stored in a byte.  Input is scanned and the bits corresponding to "on" segments are set.
     // Patterns of bits corresponding to valid numbers are mapped to the corresponding character.
-    register: [u8; 9],
+    register: Vec<u8>,
+
+    // Segments of each digit which held a character that could not be read in lenient mode.
+    // These may be either on or off.
+    uncertain: Vec<u8>,
+
+    // Flags for digits containing a character which cannot appear in a glyph
+    malformed: Vec<bool>,
+
+    // Errors found in the current entry when collecting errors
+    errors: Vec<ParseError>,
 
     // The current line number
     line_number: usize,
 
+    // Number of columns before the glyphs.  None until the margin has been detected.
+    margin: Option<usize>,
+
+    // Scale of the glyphs.  None until the scale has been detected.
+    scale: Option<usize>,
+
+    // Lines of the first entry held back until the margin and scale have been detected
+    held: Vec<String>,
+
+    // Row within the entry which the next line will be read as
+    next_row: usize,
+
     // Flag to skip over lines in case of error
     skip: bool,
+
+    // Flag set while every row of the entry being read is blank, such as an empty slot for one of
+    // several entries laid out side by side.  A blank entry is not reported.
+    blank: bool,
+
+    // Flag to skip over lines until one could start an entry after framing was lost
+    resync: bool,
+
+    // Statuses produced by a line in addition to the one returned for it, with the line number
+    // each belongs to
+    pending: VecDeque<(Status, usize)>,
+
+    // Position of the entries read by this parser within each band of lines, from 0
+    field: usize,
+
+    // Parser settings
+    config: ParserConfig,
 }
 
 // Parsing status for current entry
 #[derive(Debug)]
 pub enum Status {
     // Entry parsed successfully.  Account number is available.
-    Success(String),
+    Success {
+        // parsed number
+        account_number: String,
+
+        // segments of each digit as scanned
+        segments: Vec<u8>,
+
+        // set if a digit was drawn with a variant of its glyph
+        non_canonical: bool,
+    },
 
     // Entry parsed but one or more digits are unreadable
     BadDigits {
@@ -29,15 +202,22 @@ pub enum Status {
         // parsed number with '?' in place of unreadable digits
         account_number: String,
 
-        // possible account numbers that are very similar to the parsed number
-        alternates: Vec<String>,
+        // segments of each digit as scanned
+        segments: Vec<u8>,
+
+        // segments of each digit which held a character that could not be read in lenient
+        // mode, and may be either on or off
+        uncertain: Vec<u8>,
+
+        // set if a readable digit was drawn with a variant of its glyph
+        non_canonical: bool,
     },
 
     // Error occurred.  Error field is populated with details.
     // For example an invalid character will produce an error.
     Error {
-        // Message describing the error
-        message : String,
+        // Nature of the error
+        kind : ErrorKind,
 
         // Line number of input where error occurred
         line_number : usize,
@@ -49,18 +229,190 @@ pub enum Status {
         row : usize,
     },
 
+    // Errors occurred and were collected across the whole entry
+    Errors {
+        // parsed number with '?' in place of digits which are unreadable or contain errors
+        account_number : String,
+
+        // Every error found in the entry in the order it was read
+        errors : Vec<ParseError>,
+    },
+
+    // The framing of entries was reset because a line did not fit the row it fell on
+    Warning {
+        // Nature of the realignment
+        kind : WarningKind,
+
+        // Line number of input where framing was reset
+        line_number : usize,
+    },
+
     // Not all rows of current entry have been parsed.  Continue parsing lines.
     Incomplete
 }
 
+// Kinds of realignment warning
+#[derive(Debug, Clone, PartialEq)]
+pub enum WarningKind {
+    // A line could not belong to the entry being read so the entry was dropped and a new entry
+    // started
+    AbandonedEntry {
+        // Number of rows of the dropped entry which had been read
+        rows: usize,
+
+        // Number of rows of glyphs in an entry
+        expected: usize,
+    },
+
+    // A line in place of a top row looks like the middle or bottom row of a glyph.  Lines are
+    // skipped until one could start an entry.
+    UnexpectedLowerRow,
+
+    // The top row of the next entry was found in place of a separator line
+    MissingSeparator,
+}
+
+impl WarningKind {
+    // Short name of the kind for machine readable output
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::AbandonedEntry {..} => "abandoned_entry",
+            WarningKind::UnexpectedLowerRow => "unexpected_lower_row",
+            WarningKind::MissingSeparator => "missing_separator",
+        }
+    }
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WarningKind::AbandonedEntry { rows, expected } =>
+                write!(f, "Entry abandoned after {} of {} rows; realigned at this line.", rows, expected),
+            WarningKind::UnexpectedLowerRow =>
+                write!(f, "Expected the top row of an entry; skipping to the next entry."),
+            WarningKind::MissingSeparator =>
+                write!(f, "Separator line missing; realigned at this line."),
+        }
+    }
+}
+
+// An error found while parsing an entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    // Nature of the error
+    pub kind : ErrorKind,
+
+    // Line number of input where error occurred
+    pub line_number : usize,
+
+    // Column number of input where error occurred
+    pub col : usize,
+
+    // Row within the entry where error occurred
+    pub row : usize,
+}
+
+// Kinds of parsing error
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    // A glyph contains a character which cannot appear at its position
+    UnexpectedChar {
+        // Character found in the input
+        found: char,
+
+        // Characters allowed at the position
+        expected: Vec<char>,
+    },
+
+    // A line has characters beyond the last digit
+    LineTooLong {
+        // First character found beyond the last digit
+        found: char,
+
+        // Number of characters allowed in a line
+        max_len: usize,
+    },
+
+    // The input ended part way through the rows of an entry
+    TruncatedEntry {
+        // Number of rows read before the input ended
+        rows: usize,
+
+        // Number of rows in an entry
+        expected: usize,
+    },
+
+    // The line following the glyphs of an entry is not blank
+    NonBlankSeparator {
+        // Character found in the separator line
+        found: char,
+    },
+}
+
+impl ErrorKind {
+    // Short name of the kind for machine readable output
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::UnexpectedChar {..} => "unexpected_char",
+            ErrorKind::LineTooLong {..} => "line_too_long",
+            ErrorKind::TruncatedEntry {..} => "truncated_entry",
+            ErrorKind::NonBlankSeparator {..} => "non_blank_separator",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnexpectedChar { found, expected } => {
+                let expected : Vec<String> = expected
+                    .iter()
+                    .map(|ch| if *ch == ' ' { "space".to_string() } else { format!("'{}'", ch) })
+                    .collect();
+                write!(f, "Expected {} but found '{}'.", expected.join(" or "), found)
+            }
+            ErrorKind::LineTooLong {..} => write!(f, "Input line is too long."),
+            ErrorKind::TruncatedEntry { rows, expected } =>
+                write!(f, "Input ended after {} of {} rows of an entry.", rows, expected),
+            ErrorKind::NonBlankSeparator { found } =>
+                write!(f, "Expected blank separator line but found '{}'.", found),
+        }
+    }
+}
+
+// Characters allowed in a cell whose "on" character is given, '\0' if the cell is always blank
+pub fn expected_chars(on: char) -> Vec<char> {
+    if on == '\0' { vec![' '] } else { vec![' ', on] }
+}
+
 impl Parser {
 
-    // Create a new parser
-    pub fn new() -> Parser {
+    // Create a new parser with the given settings for one of the entries laid out side by side
+    // in each band of lines
+    pub fn new(mut config: ParserConfig, field: usize) -> Parser {
+        config.font = config.reading_font();
         Parser {
-            register: [0; 9],
+            register: vec![0; config.digits],
+            uncertain: vec![0; config.digits],
+            malformed: vec![false; config.digits],
+            errors: Vec::new(),
             line_number: 0,
+            margin: match config.left_margin {
+                LeftMargin::Auto => None,
+                LeftMargin::Fixed(columns) => Some(columns),
+            },
+            scale: match config.scale {
+                Scale::Auto => None,
+                Scale::Fixed(scale) => Some(scale.max(1)),
+            },
+            held: Vec::new(),
+            next_row: 0,
             skip: false,
+            blank: true,
+            resync: false,
+            pending: VecDeque::new(),
+            field,
+            config,
         }
     }
 
@@ -69,11 +421,50 @@ impl Parser {
         self.line_number
     }
 
+    // Take the next status produced by an earlier line along with its line number
+    pub fn next_pending(&mut self) -> Option<(Status, usize)> {
+        self.pending.pop_front()
+    }
+
     // Process a line of input
     pub fn process_line(&mut self, line: &str) -> Status {
+        let (Some(margin), Some(_)) = (self.margin, self.scale) else {
+            self.held.push(line.to_string());
+            if self.holds_first_entry() {
+                self.release_held_lines();
+            }
+            return Status::Incomplete;
+        };
+
         self.line_number += 1;
+        let start = margin + self.field_start();
+        let stripped : String = if self.field + 1 < self.config.fields {
+            line.chars().skip(start).take(self.entry_width()).collect()
+        } else {
+            line.chars().skip(start).collect()
+        };
+        let line = stripped.as_str();
+
+        if self.resync {
+            if self.has_vertical_stroke(line) {
+                return Status::Incomplete;
+            }
+
+            // A blank line is taken to be the separator before the next entry
+            self.resync = false;
+            if self.config.separators && is_blank(line) {
+                return Status::Incomplete;
+            }
+        }
+
+        let row = self.next_row;
+        if self.config.realign {
+            if let Some(status) = self.realign(line, row) {
+                return status;
+            }
+        }
+        self.next_row = (row + 1) % self.entry_lines();
 
-        let row = self.row();
         if row == 0 {
             self.skip = false;
             self.clear_register();
@@ -81,96 +472,431 @@ impl Parser {
             return Status::Incomplete;
         }
 
-        let mut col = 0;
-        for ch in line.chars() {
-            let pos = col % 3;
-            let dig = col / 3;
-            let on = on_char(row, pos);
-            let bit_pos = bit_pos(row, pos);
+        if row == self.glyph_rows() {
+            return self.process_separator(line);
+        }
+        self.process_glyph_row(line, row)
+    }
 
-            if !ch.is_whitespace() && dig > 8 {
-                return self.build_error(format!("Input line is too long."), col);
-            } else if on == '\0' {
-                if ch != ' ' {
-                    return self.build_error(format!("Expected space but found '{}'.", ch), col);
+    // Check that a line fits the row it falls on.  If it does not, the framing is reset to the
+    // most likely row and a warning is returned.
+    fn realign(&mut self, line: &str, row: usize) -> Option<Status> {
+        match row {
+            // Every digit has a vertical stroke in both its middle and bottom rows, which never
+            // appears in the top row
+            0 if self.looks_like_lower_row(line) => {
+                self.resync = true;
+                Some(self.warning(WarningKind::UnexpectedLowerRow))
+            }
+            1.. if row < self.glyph_rows() && is_blank(line) && !self.blank => {
+                self.next_row = 0;
+                Some(self.warning(WarningKind::AbandonedEntry { rows: row, expected: self.glyph_rows() }))
+            }
+            1.. if row < self.glyph_rows() && self.looks_like_top_row(line) => {
+                let warning = self.warning(WarningKind::AbandonedEntry { rows: row, expected: self.glyph_rows() });
+                self.start_entry(line);
+                Some(warning)
+            }
+            _ if row == self.glyph_rows() && self.looks_like_top_row(line) => {
+                if !self.skip && !self.blank {
+                    let entry = self.complete_entry();
+                    self.pending.push_back((entry, self.line_number - 1));
                 }
-            } else {
-                if ch == on {
-                    self.register[dig] |= 1 << bit_pos;
-                } else if ch != ' ' {
-                    return self.build_error(format!("Expected space or '{}' but found '{}'.", on, ch), col);
+                let warning = self.warning(WarningKind::MissingSeparator);
+                self.pending.push_back((warning, self.line_number));
+                self.start_entry(line);
+                Some(Status::Incomplete)
+            }
+            _ => None,
+        }
+    }
+
+    // Start a new entry with a line as its top row.  Any status it produces is left pending.
+    fn start_entry(&mut self, line: &str) {
+        self.next_row = 1;
+        self.skip = false;
+        self.clear_register();
+        let status = self.process_glyph_row(line, 0);
+        if !matches!(status, Status::Incomplete) {
+            self.pending.push_back((status, self.line_number));
+        }
+    }
+
+    // Read a row of glyphs into the register
+    fn process_glyph_row(&mut self, line: &str, row: usize) -> Status {
+        if !is_blank(line) {
+            self.blank = false;
+        }
+        for (col, ch) in line.chars().enumerate() {
+            if let Some(kind) = self.read_cell(row, col, ch) {
+                if !self.config.collect_errors {
+                    return self.build_error(kind, col);
+                }
+
+                // Only a bad cell leaves the rest of the line worth reading
+                let bad_cell = matches!(kind, ErrorKind::UnexpectedChar {..});
+                self.add_error(kind, col);
+                if !bad_cell {
+                    break;
                 }
             }
-            col += 1;
         }
 
-        return if row < 3 {
+        if row + 1 < self.glyph_rows() || self.config.separators {
             Status::Incomplete
         } else {
-            let (success, account_number, alternates) = self.read_register();
-            if success {
-                Status::Success(account_number)
-            } else {
-                Status::BadDigits {account_number, alternates}
+            self.complete_entry()
+        }
+    }
+
+    // Check that the line following the glyphs of an entry is blank, then decode the entry
+    fn process_separator(&mut self, line: &str) -> Status {
+        if let Some((col, found)) = line.chars().enumerate().find(|(_, ch)| !ch.is_whitespace()) {
+            let kind = ErrorKind::NonBlankSeparator { found };
+            if !self.config.collect_errors {
+                return self.build_error(kind, col);
             }
-        };
+            self.add_error(kind, col);
+        }
+        self.complete_entry()
     }
 
-    // Get row within the entry currently being parsed
-    fn row(&self) -> usize {
-        (self.line_number - 1) % 4
+    // Signal the end of the input.  An entry whose glyphs are all present is decoded even though
+    // its separator line is missing.  Any other partial entry is reported as truncated.
+    pub fn finish(&mut self) -> Status {
+        if self.margin.is_none() || self.scale.is_none() {
+            self.release_held_lines();
+        }
+
+        let status = self.finish_entry();
+        if self.pending.is_empty() {
+            return status;
+        }
+
+        // Keep the statuses of the held lines ahead of the final one
+        if !matches!(status, Status::Incomplete) {
+            self.pending.push_back((status, self.line_number));
+        }
+        Status::Incomplete
+    }
+
+    // Complete a partial entry at the end of the input
+    fn finish_entry(&mut self) -> Status {
+        let rows = self.next_row;
+        if rows == 0 || self.skip || self.blank {
+            return Status::Incomplete;
+        }
+
+        self.skip = true;
+        if rows < self.glyph_rows() {
+            let kind = ErrorKind::TruncatedEntry { rows, expected: self.glyph_rows() };
+            if !self.config.collect_errors {
+                return self.build_error(kind, 0);
+            }
+            self.add_error(kind, 0);
+        }
+        self.complete_entry()
+    }
+
+    // Test if the lines held back cover the first entry, so that its layout can be detected.  The
+    // entry is assumed to be at the standard scale until a stroke has been seen.
+    fn holds_first_entry(&self) -> bool {
+        let scale = self.scale.or_else(|| self.detect_scale()).unwrap_or(1);
+        self.held.len() >= glyph_rows(scale)
     }
 
-    // Read account number from register and reset register to empty
-    fn read_register(&self) -> (bool, String, Vec<String>) {
-        let mut buffer : [u8;9] = [0;9];
+    // Detect the scale and margin from the lines held back and then parse them.  Their statuses
+    // are left pending.
+    fn release_held_lines(&mut self) {
+        if self.scale.is_none() {
+            self.scale = Some(self.detect_scale().unwrap_or(1));
+        }
+        if self.margin.is_none() {
+            self.margin = Some(self.detect_margin());
+        }
+        for line in std::mem::take(&mut self.held) {
+            let status = self.process_line(&line);
+            if !matches!(status, Status::Incomplete) {
+                self.pending.push_back((status, self.line_number));
+            }
+        }
+    }
 
-        let mut bad_cnt = 0; // count of illegible digits
-        let mut last_bad = 0usize; // index of last illegible digit
-        for index in 0..9 {
-            let digit = read_register_digit(self.register[index]);
-            buffer[index] = digit;
-            if digit == ILLEGIBLE {
-                bad_cnt += 1;
-                last_bad = index;
+    // Find the scale from the strokes of the first entry.  Every bar is a run of as many '_' as the
+    // scale, and a vertical stroke is a run of that many '|' unless it meets the stroke of the
+    // next digit, so the shortest run gives the scale.  None if no strokes have been seen.
+    fn detect_scale(&self) -> Option<usize> {
+        let mut shortest : Option<usize> = None;
+        for line in &self.held {
+            let mut run = 0;
+            let mut previous = None;
+            for ch in line.chars().chain([' ']) {
+                let stroke = if self.is_horizontal_stroke(ch) { Some('_') } else if self.is_vertical_stroke(ch) { Some('|') } else { None };
+                if stroke.is_some() && stroke == previous {
+                    run += 1;
+                } else {
+                    if run > 0 {
+                        shortest = Some(shortest.map_or(run, |shortest| shortest.min(run)));
+                    }
+                    run = if stroke.is_some() { 1 } else { 0 };
+                }
+                previous = stroke;
             }
         }
-        let account_number = str::from_utf8(&buffer).unwrap().to_string();
-
-        let mut alts = Vec::new();
-        match bad_cnt {
-            0 => (true, account_number, alts),
-            1 => {
-                let alt_digits = find_register_digit_close_matches(self.register[last_bad]);
-                alt_digits.iter().for_each(|dig| {
-                    buffer[last_bad] = *dig;
-                    alts.push(str::from_utf8(&buffer).unwrap().to_string());
-                });
-                (false, account_number, alts)
+        shortest
+    }
+
+    // Find the margin from the rows of the first entry.  Every glyph has a stroke within two
+    // thirds of its width of its left edge and of its right edge, so the glyphs of the first and
+    // last digits bound the margin, and bars are only drawn in the middle third of each digit.
+    // Of the margins within those bounds, the one which places the most strokes where a glyph
+    // could draw them is taken.  No margin is assumed unless the middle and bottom rows are
+    // present and a single margin fits best, as strokes outside the bounds are more likely to be
+    // an extra digit.
+    fn detect_margin(&self) -> usize {
+        let strokes : Vec<(usize, bool)> = self.held
+            .iter()
+            .flat_map(|line| line.chars().enumerate())
+            .filter(|(_, ch)| self.is_horizontal_stroke(*ch) || self.is_vertical_stroke(*ch))
+            .map(|(col, ch)| (col, self.is_horizontal_stroke(ch)))
+            .collect();
+        let rows = self.held.iter().filter(|line| self.has_vertical_stroke(line)).count();
+        let (Some(first), Some(last)) = (strokes.iter().map(|(col, _)| *col).min(), strokes.iter().map(|(col, _)| *col).max()) else {
+            return 0;
+        };
+        if rows < 2 {
+            return 0;
+        }
+
+        let scale = self.scale();
+        let width = self.band_width();
+        let fits : Vec<(usize, usize)> = (first.saturating_sub(2 * scale)..=first)
+            .filter(|margin| last < margin + width && last + scale + 1 >= margin + width)
+            .map(|margin| (margin, strokes.iter().filter(|(col, horizontal)| self.fits_glyph(col - margin, *horizontal)).count()))
+            .collect();
+        let best = fits.iter().map(|(_, count)| *count).max();
+        match fits.iter().filter(|(_, count)| Some(*count) == best).collect::<Vec<_>>()[..] {
+            [(margin, _)] => *margin,
+            _ => 0,
+        }
+    }
+
+    // Test if a stroke could be drawn at a column of the band: bars in the middle third of a
+    // digit and vertical strokes in the outer thirds, with none in the gaps between fields
+    fn fits_glyph(&self, col: usize, horizontal: bool) -> bool {
+        let col = col % (self.entry_width() + self.config.field_gap);
+        col < self.entry_width() && (col % (3 * self.scale()) / self.scale() == 1) == horizontal
+    }
+
+    // Read a character of the input into the register.  Returns the error if the character
+    // cannot appear at its position.  Errors report the character as it was found rather than
+    // the stroke it stands for.
+    fn read_cell(&mut self, text_row: usize, col: usize, ch: char) -> Option<ErrorKind> {
+        let stroke = self.stroke(ch);
+        let scale = self.scale();
+        let pos = col % (3 * scale) / scale;
+        let dig = col / (3 * scale);
+
+        // Collapse a scaled glyph onto the 3x3 grid.  A bar is only drawn on the last text row of
+        // each half of the glyph while vertical strokes run down every text row.
+        let row = if text_row == 0 { 0 } else { (text_row - 1) / scale + 1 };
+        let on = if pos == 1 && !text_row.is_multiple_of(scale) { '\0' } else { on_char(row, pos) };
+
+        if !ch.is_whitespace() && dig >= self.config.digits {
+            return Some(ErrorKind::LineTooLong { found: ch, max_len: self.entry_width() });
+        } else if stroke == on && on != '\0' {
+            self.register[dig] |= 1 << bit_pos(row, pos);
+        } else if ch != ' ' && self.config.lenient {
+            // Noise where no segment can be is ignored
+            if on == '\0' {
+                return None;
             }
-            _ => (false, account_number, alts)
+            if self.config.noise_map.iter().any(|(noise, noise_stroke)| *noise == stroke && *noise_stroke == on) {
+                self.register[dig] |= 1 << bit_pos(row, pos);
+            } else {
+                self.uncertain[dig] |= 1 << bit_pos(row, pos);
+            }
+        } else if ch != ' ' {
+            self.malformed[dig] = true;
+            return Some(ErrorKind::UnexpectedChar { found: ch, expected: expected_chars(on) });
         }
+        None
+    }
+
+    // Decode the entry once all of its rows have been read.  When errors have been collected
+    // they are returned together along with whatever digits could be read.
+    fn complete_entry(&mut self) -> Status {
+        if self.blank && self.errors.is_empty() {
+            return Status::Incomplete;
+        }
+        if self.errors.is_empty() {
+            return decode_uncertain_segments(&self.register, &self.uncertain, &self.config);
+        }
+
+        let buffer : Vec<u8> = (0..self.register.len())
+            .map(|dig| {
+                if self.malformed[dig] || self.uncertain[dig] != 0 {
+                    ILLEGIBLE
+                } else {
+                    read_register_digit(self.register[dig], &self.config.font)
+                }
+            })
+            .collect();
+        Status::Errors {
+            account_number: str::from_utf8(&buffer).unwrap().to_string(),
+            errors: std::mem::take(&mut self.errors),
+        }
+    }
+
+    // Get row within the entry currently being parsed
+    fn row(&self) -> usize {
+        (self.next_row + self.entry_lines() - 1) % self.entry_lines()
+    }
+
+    // Scale of the glyphs, the standard scale until it has been detected
+    fn scale(&self) -> usize {
+        self.scale.unwrap_or(1)
+    }
+
+    // Number of rows of glyphs in an entry, not counting the separator line
+    fn glyph_rows(&self) -> usize {
+        glyph_rows(self.scale())
+    }
+
+    // Number of columns taken by the glyphs of an entry
+    fn entry_width(&self) -> usize {
+        self.config.digits * 3 * self.scale()
+    }
+
+    // Column where the entries of this parser start, not counting the margin
+    fn field_start(&self) -> usize {
+        self.field * (self.entry_width() + self.config.field_gap)
+    }
+
+    // Number of columns taken by all the entries of a band
+    fn band_width(&self) -> usize {
+        let fields = self.config.fields.max(1);
+        fields * self.entry_width() + (fields - 1) * self.config.field_gap
+    }
+
+    // Column in the original line of a column of the entry
+    fn original_col(&self, col : usize) -> usize {
+        col + self.margin.unwrap_or(0) + self.field_start()
+    }
+
+    // Number of lines of input in each entry
+    fn entry_lines(&self) -> usize {
+        if self.config.separators { self.glyph_rows() + 1 } else { self.glyph_rows() }
     }
 
     // Clear contents of register
     fn clear_register(&mut self) {
+        self.blank = true;
         self.register.fill(0);
+        self.uncertain.fill(0);
+        self.malformed.fill(false);
+        self.errors.clear();
     }
 
     // Build a parsing error
-    fn build_error(&mut self, message : String, col : usize) -> Status {
+    fn build_error(&mut self, kind : ErrorKind, col : usize) -> Status {
         self.skip = true;
         Status::Error {
-            message,
+            kind,
             line_number: self.line_number,
-            col,
+            col: self.original_col(col),
             row: self.row(),
         }
     }
+
+    // Build a realignment warning for the current line
+    fn warning(&self, kind : WarningKind) -> Status {
+        Status::Warning { kind, line_number: self.line_number }
+    }
+
+    // Read a character which stands for a stroke as that stroke.  Other characters are unchanged.
+    fn stroke(&self, ch : char) -> char {
+        self.config.stroke_map.iter().find(|(equivalent, _)| *equivalent == ch).map_or(ch, |(_, stroke)| *stroke)
+    }
+
+    // Test if a character is a vertical stroke, including characters and noise which stand for one
+    fn is_vertical_stroke(&self, ch : char) -> bool {
+        let ch = self.stroke(ch);
+        ch == '|' || (self.config.lenient && self.config.noise_map.contains(&(ch, '|')))
+    }
+
+    // Test if a character is a horizontal stroke, including characters and noise which stand for one
+    fn is_horizontal_stroke(&self, ch : char) -> bool {
+        let ch = self.stroke(ch);
+        ch == '_' || (self.config.lenient && self.config.noise_map.contains(&(ch, '_')))
+    }
+
+    // Test if a line has a vertical stroke anywhere
+    fn has_vertical_stroke(&self, line : &str) -> bool {
+        line.chars().any(|ch| self.is_vertical_stroke(ch))
+    }
+
+    // Test if a line could be the top row of an entry: it has horizontal strokes but no
+    // vertical ones
+    fn looks_like_top_row(&self, line : &str) -> bool {
+        let horizontal = line.chars().any(|ch| self.is_horizontal_stroke(ch));
+        horizontal && !self.has_vertical_stroke(line)
+    }
+
+    // Test if a line looks like the middle or bottom row of an entry: every digit with content
+    // has a vertical stroke
+    fn looks_like_lower_row(&self, line : &str) -> bool {
+        let chars : Vec<char> = line.chars().collect();
+        let mut cells = chars.chunks(3 * self.scale()).filter(|cell| cell.iter().any(|ch| !ch.is_whitespace())).peekable();
+        cells.peek().is_some() && cells.all(|cell| cell.iter().any(|ch| self.is_vertical_stroke(*ch)))
+    }
+
+    // Record a parsing error to be reported when the entry is complete
+    fn add_error(&mut self, kind : ErrorKind, col : usize) {
+        self.errors.push(ParseError {
+            kind,
+            line_number: self.line_number,
+            col: self.original_col(col),
+            row: self.row(),
+        });
+    }
+}
+
+// Test if a line holds nothing but whitespace
+fn is_blank(line : &str) -> bool {
+    line.chars().all(char::is_whitespace)
+}
+
+// Number of rows of glyphs in an entry at a scale
+fn glyph_rows(scale: usize) -> usize {
+    (GLYPH_ROWS - 1) * scale.max(1) + 1
+}
+
+// Read the account number from the segments of each digit where some segments may be uncertain.
+// A digit with an uncertain segment is illegible.
+fn decode_uncertain_segments(register: &[u8], uncertain: &[u8], config: &ParserConfig) -> Status {
+    let buffer : Vec<u8> = register
+        .iter()
+        .zip(uncertain)
+        .map(|(reg, uncertain)| if *uncertain == 0 { read_register_digit(*reg, &config.font) } else { ILLEGIBLE })
+        .collect();
+
+    let account_number = str::from_utf8(&buffer).unwrap().to_string();
+    let non_canonical = register
+        .iter()
+        .zip(&buffer)
+        .any(|(reg, ch)| *ch != ILLEGIBLE && !config.font.is_canonical(*reg));
+    if !buffer.contains(&ILLEGIBLE) {
+        return Status::Success { account_number, segments: register.to_vec(), non_canonical };
+    }
+
+    // A mark was seen where a segment is uncertain so it is most likely on
+    let segments = register.iter().zip(uncertain).map(|(reg, uncertain)| reg | uncertain).collect();
+    Status::BadDigits {account_number, segments, uncertain: uncertain.to_vec(), non_canonical}
 }
 
 // Determine the character that indicates an "on" element
-fn on_char(row: usize, col: usize) -> char {
+pub fn on_char(row: usize, col: usize) -> char {
     match row << 4 | col {
         0x00 => '\0',
         0x01 => '_',
@@ -185,43 +911,45 @@ fn on_char(row: usize, col: usize) -> char {
     }
 }
 
-// Find digits that are a close match to the register element
-fn find_register_digit_close_matches(reg_element: u8) -> Vec<u8> {
-    let mut close_matches = Vec::new();
-    for n in 0..=6 {
-        let close_match = read_register_digit(reg_element ^ (1 << n));
-        if close_match != ILLEGIBLE {
-            close_matches.push(close_match as u8);
-        }
+// Segments which are "on" for each of the digits '0' through '9'
+/*
+Bit positions for each segment
+-0-
+123
+456
+ */
+pub const DIGIT_SEGMENTS : [u8; 10] = [
+    0b01111011, // 0
+    0b01001000, // 1
+    0b00111101, // 2
+    0b01101101, // 3
+    0b01001110, // 4
+    0b01100111, // 5
+    0b01110111, // 6
+    0b01001001, // 7
+    0b01111111, // 8
+    0b01101111, // 9
+];
+
+// Determine the output character associated with a value in the register
+fn read_register_digit(reg_element: u8, font: &Font) -> u8 {
+    match font.read(reg_element) {
+        Some(ch) => ch,
+        None => ILLEGIBLE // The value doesn't correspond to a glyph of the font
     }
-    close_matches
 }
 
-// Determine the output character associated with a value in the register
-fn read_register_digit(reg_element: u8) -> u8 {
-    /*
-    Bit positions for each segment
-    -0-
-    123
-    456
-     */
-    match reg_element {
-        0b01111011 => '0' as u8,
-        0b01001000 => '1' as u8,
-        0b00111101 => '2' as u8,
-        0b01101101 => '3' as u8,
-        0b01001110 => '4' as u8,
-        0b01100111 => '5' as u8,
-        0b01110111 => '6' as u8,
-        0b01001001 => '7' as u8,
-        0b01111111 => '8' as u8,
-        0b01101111 => '9' as u8,
-        _ => ILLEGIBLE // The value doesn't correspond to a numerical digit
+// Determine the register value which displays a digit.  None if the character is not a digit.
+pub fn digit_segments(ch: u8) -> Option<u8> {
+    if ch.is_ascii_digit() {
+        Some(DIGIT_SEGMENTS[(ch - b'0') as usize])
+    } else {
+        None
     }
 }
 
 // Determine the register bit which corresponds to an element
-fn bit_pos(row: usize, col: usize) -> usize {
+pub fn bit_pos(row: usize, col: usize) -> usize {
     match row << 4 | col {
         0x01 => 0,
         0x10 => 1,
@@ -237,22 +965,15 @@ fn bit_pos(row: usize, col: usize) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{find_uncertain_corrections, Checksum};
 
-    #[test]
-    fn possible_alternative_digits() {
-        assert_eq!(find_register_digit_close_matches(0b00100110), vec![]);
-        assert_eq!(find_register_digit_close_matches(0b01111010), vec!['0' as u8]);
-        assert_eq!(find_register_digit_close_matches(0b01000000), vec!['1' as u8]);
-        assert_eq!(find_register_digit_close_matches(0b00111100), vec!['2' as u8]);
-        assert_eq!(find_register_digit_close_matches(0b01101100), vec!['3' as u8]);
-        assert_eq!(find_register_digit_close_matches(0b01000110), vec!['4' as u8]);
-        assert_eq!(find_register_digit_close_matches(0b01100110), vec!['5' as u8]);
-        assert_eq!(find_register_digit_close_matches(0b01110110), vec!['6' as u8]);
-        assert_eq!(find_register_digit_close_matches(0b01001000), vec!['7' as u8]);
-        assert_eq!(find_register_digit_close_matches(0b01111110), vec!['8' as u8]);
-        assert_eq!(find_register_digit_close_matches(0b01101101), vec!['9' as u8]);
-        assert_eq!(find_register_digit_close_matches(0b01001010), vec!['1' as u8, '4' as u8]);
-        assert_eq!(find_register_digit_close_matches(0b01111101), vec!['8' as u8, '3' as u8, '2' as u8]);
+    // Accepts every reading so that tests see all the alternates within reach of an entry
+    struct AnyReading;
+
+    impl Checksum for AnyReading {
+        fn is_valid(&self, _account_number : &str) -> bool {
+            true
+        }
     }
 
     #[test]
@@ -275,6 +996,38 @@ mod tests {
         ]));
     }
 
+    #[test]
+    fn digit_count_is_configurable() {
+        let config = ParserConfig { digits: 8, ..ParserConfig::default() };
+        assert_eq!("SUCCESS: 00000000", parse_to_string_with_config([
+            " _  _  _  _  _  _  _  _ ",
+            "| || || || || || || || |",
+            "|_||_||_||_||_||_||_||_|",
+            ""
+        ], config.clone()));
+
+        assert_eq!("ERROR: 1:25: row 0: Input line is too long.", parse_to_string_with_config([
+            " _  _  _  _  _  _  _  _  _ ",
+            "| || || || || || || || || |",
+            "|_||_||_||_||_||_||_||_||_|",
+            ""
+        ], config));
+
+        assert_eq!("SUCCESS: 123456789012", parse_to_string_with_config([
+            "    _  _     _  _  _  _  _  _     _ ",
+            "  | _| _||_||_ |_   ||_||_|| |  | _|",
+            "  ||_  _|  | _||_|  ||_| _||_|  ||_ ",
+            ""
+        ], ParserConfig { digits: 12, ..ParserConfig::default() }));
+
+        assert_eq!("ILLEGIBLE: 1234567890?? []", parse_to_string_with_config([
+            "    _  _     _  _  _  _  _  _ ",
+            "  | _| _||_||_ |_   ||_||_|| |",
+            "  ||_  _|  | _||_|  ||_| _||_|",
+            ""
+        ], ParserConfig { digits: 12, ..ParserConfig::default() }));
+    }
+
     #[test]
     fn can_parse_a_correctly_formatted_number() {
 
@@ -366,14 +1119,15 @@ mod tests {
 
     #[test]
     fn illegible_digits_receive_placeholder() {
-        assert_eq!("ILLEGIBLE: 49006771? [\"490067715\", \"490067713\"]", parse_to_string([
+        assert_eq!("ILLEGIBLE: 49006771? [\"490067713\", \"490067715\"]", parse_to_string([
             "    _  _  _  _  _  _     _ ",
             "|_||_|| || ||_   |  |  | _ ",
             "  | _||_||_||_|  |  |  | _|",
             ""
         ]));
 
-        assert_eq!("ILLEGIBLE: 1234?678? []", parse_to_string([
+        // Every combination of readings of the illegible digits is tried
+        assert_eq!("ILLEGIBLE: 1234?678? [\"123436789\", \"123456789\"]", parse_to_string([
             "    _  _     _  _  _  _  _ ",
             "  | _| _||_| _ |_   ||_||_|",
             "  ||_  _|  | _||_|  ||_| _ ",
@@ -381,6 +1135,20 @@ mod tests {
         ]));
     }
 
+    #[test]
+    fn combinations_of_illegible_digits_are_capped() {
+        let lines = [
+            "    _  _     _  _  _  _  _ ",
+            "  | _| _||_| _ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _ ",
+            ""
+        ];
+        assert_eq!("ILLEGIBLE: 1234?678? [\"123436789\", \"123456789\"]", parse_to_string_with_config(lines,
+            ParserConfig { max_combinations: 2, ..ParserConfig::default() }));
+        assert_eq!("ILLEGIBLE: 1234?678? []", parse_to_string_with_config(lines,
+            ParserConfig { max_combinations: 1, ..ParserConfig::default() }));
+    }
+
     #[test]
     fn illegal_characters_produce_an_error() {
         // Character not in the set (' ', '|', '_')
@@ -422,10 +1190,471 @@ mod tests {
         ]));
     }
 
+    #[test]
+    fn errors_carry_their_kind() {
+        let status = parse_entry([
+            "    _  _     _  _  _ Q_  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            ""
+        ], ParserConfig::default());
+        assert!(matches!(status, Status::Error { kind: ErrorKind::UnexpectedChar { found: 'Q', ref expected }, .. }
+            if expected == &[' ']));
+
+        let status = parse_entry([
+            " _  _  _  _  _  _  _  _  _  _ ",
+            "| || || || || || || || || || |",
+            "|_||_||_||_||_||_||_||_||_||_|",
+            ""
+        ], ParserConfig::default());
+        assert!(matches!(status, Status::Error { kind: ErrorKind::LineTooLong { found: '_', max_len: 27 }, .. }));
+
+        assert_eq!("ERROR: 4:3: row 3: Expected blank separator line but found '-'.", parse_to_string([
+            "    _  _     _  _  _  _  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            "   ----"
+        ]));
+    }
+
+    #[test]
+    fn separator_line_must_be_blank() {
+        // Glyphs where the separator should be
+        assert_eq!("ERROR: 4:1: row 3: Expected blank separator line but found '_'.", parse_to_string_with_config([
+            "    _  _     _  _  _  _  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            " _  _  _ "
+        ], ParserConfig { realign: false, ..ParserConfig::default() }));
+
+        assert_eq!("ERROR: 4:0: row 3: Expected blank separator line but found '|'.", parse_to_string([
+            "    _  _     _  _  _  _  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            "| || |"
+        ]));
+
+        assert_eq!("SUCCESS: 123456789", parse_to_string([
+            "    _  _     _  _  _  _  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            "  \t  "
+        ]));
+    }
+
+    #[test]
+    fn entries_without_separators() {
+        let mut parser = Parser::new(ParserConfig { separators: false, ..ParserConfig::default() }, 0);
+        assert!(is_incomplete(&parser.process_line("    _  _     _  _  _  _  _ ")));
+        assert!(is_incomplete(&parser.process_line("  | _| _||_||_ |_   ||_||_|")));
+        assert_eq!("123456789".to_string(), get_account_number(parser.process_line("  ||_  _|  | _||_|  ||_| _|")));
+        assert!(is_incomplete(&parser.process_line(" _  _  _  _  _  _  _  _  _ ")));
+        assert!(is_incomplete(&parser.process_line("| || || || || || || || || |")));
+        assert_eq!("000000000".to_string(), get_account_number(parser.process_line("|_||_||_||_||_||_||_||_||_|")));
+        assert!(is_incomplete(&parser.finish()));
+
+        parser.process_line("    _  _     _  _  _  _  _ ");
+        let status = parser.finish();
+        assert!(matches!(status, Status::Error { kind: ErrorKind::TruncatedEntry { rows: 1, .. }, line_number: 7, row: 0, .. }));
+    }
+
+    #[test]
+    fn realigns_after_a_missing_line() {
+        let mut parser = Parser::new(ParserConfig::default(), 0);
+        parser.process_line("    _  _     _  _  _  _  _ ");
+        parser.process_line("  | _| _||_||_ |_   ||_||_|");
+        let status = parser.process_line("");
+        assert!(matches!(status, Status::Warning { kind: WarningKind::AbandonedEntry { rows: 2, expected: 3 }, line_number: 3 }));
+
+        // Top row where a middle row should be
+        parser.process_line(" _  _  _  _  _  _  _  _  _ ");
+        let status = parser.process_line("    _  _     _  _  _  _  _ ");
+        assert!(matches!(status, Status::Warning { kind: WarningKind::AbandonedEntry { rows: 1, expected: 3 }, line_number: 5 }));
+        parser.process_line("  | _| _||_||_ |_   ||_||_|");
+        parser.process_line("  ||_  _|  | _||_|  ||_| _|");
+        assert_eq!("123456789".to_string(), get_account_number(parser.process_line("")));
+    }
+
+    #[test]
+    fn realigns_after_an_extra_line() {
+        let mut parser = Parser::new(ParserConfig::default(), 0);
+        parser.process_line("    _  _     _  _  _  _  _ ");
+        parser.process_line("  | _| _||_||_ |_   ||_||_|");
+        parser.process_line("  ||_  _|  | _||_|  ||_| _|");
+        assert_eq!("123456789".to_string(), get_account_number(parser.process_line("")));
+
+        // An extra blank line is read as a top row until the real top row follows it
+        assert!(is_incomplete(&parser.process_line("")));
+        let status = parser.process_line(" _  _  _  _  _  _  _  _  _ ");
+        assert!(matches!(status, Status::Warning { kind: WarningKind::AbandonedEntry { rows: 1, expected: 3 }, line_number: 6 }));
+        parser.process_line("| || || || || || || || || |");
+        parser.process_line("|_||_||_||_||_||_||_||_||_|");
+        assert_eq!("000000000".to_string(), get_account_number(parser.process_line("")));
+        assert!(parser.next_pending().is_none());
+    }
+
+    #[test]
+    fn skips_to_the_next_entry_when_a_top_row_is_missing() {
+        let mut parser = Parser::new(ParserConfig::default(), 0);
+        let status = parser.process_line("  | _| _||_||_ |_   ||_||_|");
+        assert!(matches!(status, Status::Warning { kind: WarningKind::UnexpectedLowerRow, line_number: 1 }));
+        assert!(is_incomplete(&parser.process_line("  ||_  _|  | _||_|  ||_| _|")));
+        assert!(is_incomplete(&parser.process_line("")));
+        parser.process_line(" _  _  _  _  _  _  _  _  _ ");
+        parser.process_line("| || || || || || || || || |");
+        parser.process_line("|_||_||_||_||_||_||_||_||_|");
+        assert_eq!("000000000".to_string(), get_account_number(parser.process_line("")));
+        assert!(parser.next_pending().is_none());
+    }
+
+    #[test]
+    fn realigns_after_a_missing_separator() {
+        let mut parser = Parser::new(ParserConfig::default(), 0);
+        parser.process_line("    _  _     _  _  _  _  _ ");
+        parser.process_line("  | _| _||_||_ |_   ||_||_|");
+        parser.process_line("  ||_  _|  | _||_|  ||_| _|");
+        assert!(is_incomplete(&parser.process_line(" _  _  _  _  _  _  _  _  _ ")));
+
+        let (status, line_number) = parser.next_pending().unwrap();
+        assert_eq!(("123456789".to_string(), 3), (get_account_number(status), line_number));
+        assert!(matches!(parser.next_pending(), Some((Status::Warning { kind: WarningKind::MissingSeparator, .. }, 4))));
+        assert!(parser.next_pending().is_none());
+
+        parser.process_line("| || || || || || || || || |");
+        parser.process_line("|_||_||_||_||_||_||_||_||_|");
+        assert_eq!("000000000".to_string(), get_account_number(parser.process_line("")));
+    }
+
+    #[test]
+    fn detects_left_margin_from_first_entry() {
+        let config = ParserConfig { left_margin: LeftMargin::Auto, ..ParserConfig::default() };
+        let mut parser = Parser::new(config, 0);
+        let mut statuses = Vec::new();
+        for line in [
+            "0001      _  _     _  _  _  _  _",
+            "0002    | _| _||_||_ |_   ||_||_|",
+            "0003    ||_  _|  | _||_|  ||_| _|",
+            "0004",
+            "0005      _  _     _  _  _  _  _ ",
+            "0006    | _| x||_||_ |_   ||_||_|",
+        ] {
+            statuses.push(parser.process_line(line));
+        }
+        assert!(parser.next_pending().is_none());
+        assert!(statuses[..3].iter().all(is_incomplete));
+        assert_eq!("123456789".to_string(), get_account_number(statuses.remove(3)));
+
+        // Columns are reported relative to the original line
+        assert!(matches!(statuses[4], Status::Error { kind: ErrorKind::UnexpectedChar { found: 'x', .. }, line_number: 6, col: 13, row: 1 }));
+    }
+
+    #[test]
+    fn detects_left_margin_of_entry_without_outer_strokes() {
+        // Neither 7 nor E has a stroke in its outer column
+        let config = ParserConfig { font: Font::hexadecimal(), left_margin: LeftMargin::Auto, ..ParserConfig::default() };
+        assert_eq!("SUCCESS: 7012ABCDE", parse_to_string_with_config([
+            "     _  _     _  _     _     _",
+            "      || |  | _||_||_ |   _||_",
+            "      ||_|  ||_ | ||_||_ |_||_",
+            ""
+        ], config));
+    }
+
+    #[test]
+    fn no_left_margin_is_detected_from_an_extra_digit() {
+        let config = ParserConfig { left_margin: LeftMargin::Auto, ..ParserConfig::default() };
+        let mut parser = Parser::new(config, 0);
+        parser.process_line(" _  _  _  _  _  _  _  _  _  _ ");
+        parser.process_line("| || || || || || || || || || |");
+        parser.process_line("|_||_||_||_||_||_||_||_||_||_|");
+        let (status, _) = parser.next_pending().unwrap();
+        assert!(matches!(status, Status::Error { kind: ErrorKind::LineTooLong {..}, col: 28, row: 0, .. }));
+    }
+
+    #[test]
+    fn fixed_left_margin() {
+        let config = ParserConfig { left_margin: LeftMargin::Fixed(2), ..ParserConfig::default() };
+        assert_eq!("SUCCESS: 123456789", parse_to_string_with_config([
+            "      _  _     _  _  _  _  _ ",
+            "|   | _| _||_||_ |_   ||_||_|",
+            "|   ||_  _|  | _||_|  ||_| _|",
+            "| "
+        ], config.clone()));
+
+        assert_eq!("ERROR: 2:9: row 1: Expected space or '_' but found 'x'.", parse_to_string_with_config([
+            "      _  _     _  _  _  _  _ ",
+            "    | _| x||_||_ |_   ||_||_|",
+            "    ||_  _|  | _||_|  ||_| _|",
+            ""
+        ], config));
+    }
+
+    #[test]
+    fn scaled_glyphs() {
+        let parse = |lines: &[String], config: ParserConfig| {
+            let mut parser = Parser::new(config, 0);
+            let mut statuses : Vec<Status> = lines.iter().map(|line| parser.process_line(line)).filter(is_complete).collect();
+            statuses.extend(std::iter::from_fn(|| parser.next_pending().map(|(status, _)| status)));
+            (statuses, parser.scale, parser.margin)
+        };
+
+        let mut lines = crate::render_scaled("123456789", 2);
+        lines.push(String::new());
+        let (mut statuses, ..) = parse(&lines, ParserConfig { scale: Scale::Fixed(2), ..ParserConfig::default() });
+        assert_eq!(statuses.len(), 1);
+        assert_eq!("123456789", get_account_number(statuses.remove(0)));
+
+        // A bar drawn on a row which only holds vertical strokes is reported at its original column
+        lines[1].replace_range(8..9, "_");
+        let (statuses, ..) = parse(&lines, ParserConfig { scale: Scale::Fixed(2), ..ParserConfig::default() });
+        assert!(matches!(statuses[..], [Status::Error { kind: ErrorKind::UnexpectedChar { found: '_', .. }, line_number: 2, col: 8, row: 1 }]));
+
+        // The scale and margin are detected from the first entry
+        let config = ParserConfig { scale: Scale::Auto, left_margin: LeftMargin::Auto, ..ParserConfig::default() };
+        let mut lines : Vec<String> = crate::render_scaled("490067115", 3).iter().map(|line| format!("  {}", line)).collect();
+        lines.push(String::new());
+        lines.extend(crate::render_scaled("123456789", 3).iter().map(|line| format!("  {}", line)));
+        let (mut statuses, scale, margin) = parse(&lines, config.clone());
+        assert_eq!((scale, margin), (Some(3), Some(2)));
+        assert_eq!(statuses.len(), 1);
+        assert_eq!("490067115", get_account_number(statuses.remove(0)));
+
+        let (_, scale, margin) = parse(&crate::render("123456789"), config);
+        assert_eq!((scale, margin), (Some(1), Some(0)));
+    }
+
+    #[test]
+    fn end_of_input_finishes_the_entry() {
+        let mut parser = Parser::new(ParserConfig::default(), 0);
+        assert!(is_incomplete(&parser.finish()));
+
+        parser.process_line("    _  _     _  _  _  _  _ ");
+        parser.process_line("  | _| _||_||_ |_   ||_||_|");
+        parser.process_line("  ||_  _|  | _||_|  ||_| _|");
+        assert_eq!("123456789".to_string(), get_account_number(parser.finish()));
+        assert!(is_incomplete(&parser.finish()));
+
+        let mut parser = Parser::new(ParserConfig::default(), 0);
+        parser.process_line("    _  _     _  _  _  _  _ ");
+        parser.process_line("  | _| _||_||_ |_   ||_||_|");
+        let status = parser.finish();
+        assert!(matches!(status, Status::Error { kind: ErrorKind::TruncatedEntry { rows: 2, expected: 3 }, line_number: 2, row: 1, .. }));
+        if let Status::Error { kind, .. } = status {
+            assert_eq!(kind.to_string(), "Input ended after 2 of 3 rows of an entry.");
+        }
+    }
+
+    #[test]
+    fn collects_every_error_in_an_entry() {
+        let config = ParserConfig { collect_errors: true, ..ParserConfig::default() };
+        assert_eq!(
+            "ERRORS: 12?456?8? [\
+                \"1:20: row 0: Expected space but found 'Q'.\", \
+                \"2:7: row 1: Expected space or '_' but found 'x'.\", \
+                \"3:26: row 2: Expected space or '|' but found '!'.\", \
+                \"3:28: row 2: Input line is too long.\"\
+            ]",
+            parse_to_string_with_config([
+                "    _  _     _  _  _Q _  _ ",
+                "  | _| x||_||_ |_   ||_||_|",
+                "  ||_  _|  | _||_|  ||_| _! |",
+                ""
+            ], config.clone())
+        );
+
+        // Entries without errors are decoded as usual
+        assert_eq!("SUCCESS: 123456789", parse_to_string_with_config([
+            "    _  _     _  _  _  _  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            ""
+        ], config.clone()));
+
+        // The next entry is read from a clean slate
+        let mut parser = Parser::new(config, 0);
+        parser.process_line("    _  _     _  _  _  _  _ ");
+        parser.process_line("  | _| _||_||_ |_   ||_||_|");
+        parser.process_line("  ||_  _|  | _||_|  ||_| _|");
+        assert!(matches!(parser.process_line(" -- "), Status::Errors { ref errors, .. } if errors.len() == 1));
+        parser.process_line("    _  _     _  _  _  _  _ ");
+        parser.process_line("  | _| _||_||_ |_   ||_||_|");
+        assert!(matches!(parser.finish(), Status::Errors { account_number, errors }
+            if account_number == "?????????" && errors[0].kind == ErrorKind::TruncatedEntry { rows: 2, expected: 3 }));
+    }
+
+    #[test]
+    fn alternate_stroke_characters() {
+        assert_eq!("SUCCESS: 123456789", parse_to_string([
+            "    \u{2500}  \u{2581}     _  _  _  _  _ ",
+            "  \u{2502} _| _||_||_ |_   ||_||_|",
+            "  \u{00a6}|_  _|  | _||_|  ||_| _|",
+            ""
+        ]));
+
+        // Columns are counted in characters, including the margin
+        let mut lines : Vec<String> = crate::render("123456789")
+            .iter()
+            .map(|line| format!("\u{2192} {}", line.replace('|', "\u{2502}").replace('_', "\u{2500}")))
+            .collect();
+        let start = lines[0].char_indices().nth(5).unwrap().0;
+        lines[0].replace_range(start..start + 1, "\u{2581}");
+        lines.push(String::new());
+        let config = ParserConfig { left_margin: LeftMargin::Auto, ..ParserConfig::default() };
+        let mut parser = Parser::new(config, 0);
+        let mut statuses : Vec<Status> = lines.iter().map(|line| parser.process_line(line)).filter(is_complete).collect();
+        statuses.extend(std::iter::from_fn(|| parser.next_pending().map(|(status, _)| status)));
+        assert_eq!(parser.margin, Some(2));
+        assert!(matches!(statuses[..], [Status::Error { kind: ErrorKind::UnexpectedChar { found: '\u{2581}', .. }, line_number: 1, col: 5, row: 0 }]));
+
+        // The table is configurable
+        let status = parse_entry([
+            "    \u{2500}  _     _  _  _  _  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            ""
+        ], ParserConfig { stroke_map: vec![('=', '_')], ..ParserConfig::default() });
+        assert!(matches!(status, Status::Error { kind: ErrorKind::UnexpectedChar { found: '\u{2500}', .. }, .. }));
+    }
+
+    #[test]
+    fn lenient_mode_reads_noise_as_strokes() {
+        let config = ParserConfig { lenient: true, ..ParserConfig::default() };
+        assert_eq!("SUCCESS: 123456789", parse_to_string_with_config([
+            "    -  _     _  _  _  _  _ ",
+            "  ! _| _||_|l_ |_   ||_||_|",
+            "  I|_  _|  | _||_|  ||_| _|",
+            ""
+        ], config.clone()));
+
+        // Noise where no segment can be is ignored
+        assert_eq!("SUCCESS: 123456789", parse_to_string_with_config([
+            "  # _  _     _  _  _ Q_  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            ""
+        ], config.clone()));
+
+        // The mapping is configurable
+        let status = parse_entry([
+            "    -  _     _  _  _  _  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            ""
+        ], ParserConfig { noise_map: vec![('~', '_')], ..config });
+        assert!(matches!(status, Status::BadDigits {..}));
+    }
+
+    #[test]
+    fn lenient_mode_treats_unknown_characters_as_uncertain_segments() {
+        let config = ParserConfig { lenient: true, ..ParserConfig::default() };
+
+        // The middle bar of the 3 could be either on or off, while a 7 or 9 is one segment away.
+        // Reading the 3 needs no flips, which leaves one for the other digits.
+        let status = parse_entry([
+            "    _  _     _  _  _  _  _ ",
+            "  | _| x||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            ""
+        ], config.clone());
+        let alternates = readings(&status, &config);
+        assert_eq!(alternates[0], "123456789");
+        assert!(alternates.contains(&"127456789".to_string()));
+        assert!(alternates.contains(&"129456789".to_string()));
+        assert!(alternates.contains(&"723456789".to_string()));
+
+        // Uncertain segments do not count towards the distance of a close match
+        let status = parse_entry([
+            "    _  _     _  _  _  _  _ ",
+            "  | _| #||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            ""
+        ], ParserConfig { max_digit_distance: 0, ..config.clone() });
+        assert!(matches!(status, Status::BadDigits { ref segments, ref uncertain, .. }
+            if segments[2] == DIGIT_SEGMENTS[3] && uncertain[2] != 0));
+        assert_eq!("ILLEGIBLE: 12?456789 [\"123456789\"]", parse_to_string_with_config([
+            "    _  _     _  _  _  _  _ ",
+            "  | _| #||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            ""
+        ], ParserConfig { max_digit_distance: 0, ..config.clone() }));
+
+        // Lenient mode still rejects lines which are too long
+        assert_eq!("ERROR: 1:28: row 0: Input line is too long.", parse_to_string_with_config([
+            " _  _  _  _  _  _  _  _  _  _ ",
+            "| || || || || || || || || || |",
+            "|_||_||_||_||_||_||_||_||_||_|",
+            ""
+        ], config));
+    }
+
+    #[test]
+    fn hexadecimal_font() {
+        let config = ParserConfig { font: Font::hexadecimal(), ..ParserConfig::default() };
+        assert_eq!("SUCCESS: ABCDEF012", parse_to_string_with_config([
+            " _     _     _  _  _     _ ",
+            "|_||_ |   _||_ |_ | |  | _|",
+            "| ||_||_ |_||_ |  |_|  ||_ ",
+            ""
+        ], config.clone()));
+
+        // Letters are illegible in the decimal font
+        assert_eq!("ILLEGIBLE: ??????012 []", parse_to_string_with_config([
+            " _     _     _  _  _     _ ",
+            "|_||_ |   _||_ |_ | |  | _|",
+            "| ||_||_ |_||_ |  |_|  ||_ ",
+            ""
+        ], ParserConfig::default()));
+
+        // Close matches are found among the letters
+        assert_eq!("ILLEGIBLE: ?BCDEF012 [\"6BCDEF012\", \"ABCDEF012\", \"FBCDEF012\"]", parse_to_string_with_config([
+            " _     _     _  _  _     _ ",
+            "|_ |_ |   _||_ |_ | |  | _|",
+            "| ||_||_ |_||_ |  |_|  ||_ ",
+            ""
+        ], config));
+    }
+
+    #[test]
+    fn font_from_definition() {
+        let mut definition = include_str!("../fonts/kata.font").to_string();
+        definition.push_str("\n# 7 with a left stroke\n[7]\n _\n| |\n  |\n");
+        let config = ParserConfig { font: Font::parse(&definition).unwrap(), ..ParserConfig::default() };
+        let lines = [
+            "    _  _     _  _  _  _  _ ",
+            "  | _| _||_||_ |_ | ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            ""
+        ];
+        assert_eq!("SUCCESS: 123456789 (non-canonical)", parse_to_string_with_config(lines, config));
+        assert_eq!("ILLEGIBLE: 123456?89 [\"123456789\"]", parse_to_string(lines));
+    }
+
+    #[test]
+    fn variant_glyphs() {
+        let lines = [
+            "    _  _     _     _  _  _ ",
+            "  | _| _||_||_ |_ | ||_||_|",
+            "  ||_  _|  | _||_|  ||_|  |",
+            ""
+        ];
+        let config = ParserConfig { variants: true, ..ParserConfig::default() };
+        assert_eq!("SUCCESS: 123456789 (non-canonical)", parse_to_string_with_config(lines, config.clone()));
+        assert_eq!("ILLEGIBLE: 12345??8? [\"123456784\", \"123456789\"]", parse_to_string_with_config(lines,
+            ParserConfig { max_entry_distance: 3, ..ParserConfig::default() }));
+
+        // Canonical glyphs are still recognized
+        assert_eq!("SUCCESS: 123456789", parse_to_string_with_config([
+            "    _  _     _  _  _  _  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            ""
+        ], config));
+    }
+
     #[test]
     fn recovers_after_error() {
         // Error recovery
-        let mut parser = Parser::new();
+        let mut parser = Parser::new(ParserConfig::default(), 0);
         assert!(is_incomplete(&parser.process_line("    _  _  _  _  _  _     _ ")));
         assert!(is_incomplete(&parser.process_line("|_||_|| || ||_   |  |  ||_ ")));
         assert!(is_incomplete(&parser.process_line("  | _||_||_||_|  |  |  | _|")));
@@ -441,8 +1670,8 @@ mod tests {
 
     }
 
-    fn parse_entry(lines : [&str; 4]) -> Status {
-        let mut parser = Parser::new();
+    fn parse_entry(lines : [&str; 4], config : ParserConfig) -> Status {
+        let mut parser = Parser::new(config, 0);
 
         for line in lines {
             let status = parser.process_line(line);
@@ -454,17 +1683,52 @@ mod tests {
         Status::Incomplete
     }
 
+    // Every reading within reach of an illegible entry, whatever its checksum
+    fn readings(status : &Status, config : &ParserConfig) -> Vec<String> {
+        let Status::BadDigits { segments, uncertain, .. } = status else {
+            panic!("Not BadDigits status")
+        };
+        find_uncertain_corrections(
+            segments,
+            uncertain,
+            &config.reading_font(),
+            config.max_digit_distance,
+            config.max_entry_distance,
+            config.max_combinations,
+            &AnyReading
+        ).into_iter().map(|correction| correction.account_number).collect()
+    }
+
     fn parse_to_string(lines : [&str; 4]) -> String {
-        let status = parse_entry(lines);
+        parse_to_string_with_config(lines, ParserConfig::default())
+    }
+
+    fn parse_to_string_with_config(lines : [&str; 4], config : ParserConfig) -> String {
+        let status = parse_entry(lines, config.clone());
         match status {
-            Status::Success(account_number) => {
+            Status::Success { account_number, non_canonical: false, .. } => {
                 format!("SUCCESS: {}", account_number)
             }
-            Status::BadDigits { account_number, alternates } => {
-                format!("ILLEGIBLE: {} {:?}", account_number, alternates)
+            Status::Success { account_number, non_canonical: true, .. } => {
+                format!("SUCCESS: {} (non-canonical)", account_number)
+            }
+            Status::BadDigits { ref account_number, .. } => {
+                // Only the readings of the illegible digits are listed
+                let config = ParserConfig { max_entry_distance: 0, ..config };
+                format!("ILLEGIBLE: {} {:?}", account_number, readings(&status, &config))
+            }
+            Status::Error { kind, line_number, col, row} => {
+                format!("ERROR: {}:{}: row {}: {}", line_number, col, row, kind)
+            }
+            Status::Errors { account_number, errors } => {
+                let errors : Vec<String> = errors
+                    .iter()
+                    .map(|error| format!("{}:{}: row {}: {}", error.line_number, error.col, error.row, error.kind))
+                    .collect();
+                format!("ERRORS: {} {:?}", account_number, errors)
             }
-            Status::Error { message , line_number, col, row} => {
-                format!("ERROR: {}:{}: row {}: {}", line_number, col, row, message)
+            Status::Warning { kind, line_number } => {
+                format!("WARNING: {}: {}", line_number, kind)
             }
             Status::Incomplete => {
                 String::from("Unexpected")
@@ -474,7 +1738,7 @@ mod tests {
 
     fn get_account_number(status: Status) -> String {
         match status {
-            Status::Success(account_number) => account_number,
+            Status::Success { account_number, .. } => account_number,
             _ => panic!("Status is not success"),
         }
     }
@@ -1,12 +1,22 @@
-use crate::{find_adjacent, is_checksum_valid, Parser, Status};
-use crate::Result::{Success, BadChecksum, BadDigits, Error};
+use std::collections::VecDeque;
+use crate::{find_corrections, find_uncertain_corrections, is_checksum_valid, Checksum, Correction, ErrorKind, Font, Normalizer, ParseError, Parser, ParserConfig, Status, WarningKind};
+use crate::Result::{Success, BadChecksum, BadDigits, Error, Errors, Warning};
+
+// Factor applied to the confidence for each segment which had to be flipped to reach a number
+const FLIP_PENALTY : f64 = 0.75;
+
+// Factor applied to the confidence of a number which fails the checksum
+const FAILED_CHECKSUM_PENALTY : f64 = 0.1;
 
 // Result for a single entry
 pub enum Result {
     // Account number parsed and passes checksum
     Success {
         account_number : String, // Parsed account number
-        line_number : u32        // Line number of entry
+        line_number : u32,       // Line number of entry
+        field : u32,             // Position of the entry within its band of lines, from 0
+        confidence : f64,        // Likelihood from 0.0 to 1.0 that the account number is correct
+        non_canonical : bool     // Set if a digit was drawn with a variant of its glyph
     },
 
     // Account number parsed successfully but checksum failed
@@ -15,132 +25,317 @@ pub enum Result {
         alternates : Vec<String>, // Numbers similar to the account number with valid checksum
                                   // it is likely there was a scanner misread and one of these is
                                   // the actual account number
-        line_number : u32         // Line number of entry
+        line_number : u32,        // Line number of entry
+        field : u32,              // Position of the entry within its band of lines, from 0
+        confidence : f64,         // Likelihood from 0.0 to 1.0 that the best reading is correct
+        non_canonical : bool      // Set if a digit was drawn with a variant of its glyph
     },
 
     // One or more digits was illegible
     BadDigits {
         account_number : String,  // Parsed account number. '?' character fills illegible digits
         alternates : Vec<String>, // Possible numbers found be looking for close matches for illegible digit
-        line_number : u32         // Line number of entry
+        line_number : u32,        // Line number of entry
+        field : u32,              // Position of the entry within its band of lines, from 0
+        confidence : f64,         // Likelihood from 0.0 to 1.0 that the best reading is correct
+        non_canonical : bool      // Set if a readable digit was drawn with a variant of its glyph
     },
 
     // Parse error, the input file is invalid
     Error {
-        message : String,  // Message describing the nature of the error
+        kind : ErrorKind,  // Nature of the error
         line_number : u32, // Line number where error occurred
+        field : u32,       // Position of the entry within its band of lines, from 0
         col : u32,         // Column number where error occurred
-        row : u32          // Row within the entry being parsed where the error occurred
+        row : u32,         // Row within the entry being parsed where the error occurred
+        confidence : f64   // Always 0.0 as no account number could be read
+    },
+
+    // Several parse errors collected across the entry
+    Errors {
+        account_number : String,  // Digits which could be read. '?' character fills the rest
+        errors : Vec<ParseError>, // Every error found in the entry
+        line_number : u32,        // Line number of entry
+        field : u32,              // Position of the entry within its band of lines, from 0
+        confidence : f64          // Always 0.0 as no account number could be read
+    },
+
+    // The framing of entries was reset after misaligned input.  Not an entry in itself.
+    Warning {
+        kind : WarningKind,       // Nature of the realignment
+        line_number : u32,        // Line number where framing was reset
+        field : u32,              // Position of the realigned entries within their band of lines
     },
 }
 
-// Transforms an input iterator into a processed output iterator
-pub struct Processor<I>
-    where I: Iterator<Item = String>
-{
-    // Iterator supplying input lines
-    lines: I,
+// Converts the status of a parsed entry into a Result
+pub(crate) struct Evaluator<'a> {
+    // Algorithm used to validate account numbers
+    checksum: &'a dyn Checksum,
+
+    // Number of digits in each account number
+    digits: usize,
+
+    // Maximum number of segments to flip in each digit when searching for corrections
+    max_digit_distance: u32,
+
+    // Maximum number of segments to flip in the entry when searching for corrections
+    max_entry_distance: u32,
 
-    // Input parser
-    parser: Parser,
+    // Maximum number of account numbers to try when searching for corrections
+    max_combinations: usize,
+
+    // Glyphs used to draw the characters of account numbers
+    font: Font,
 }
 
-impl<I> Processor<I>
-    where I: Iterator<Item = String>
-{
-    pub fn new(lines: I) -> Processor<I>{
-        Processor {
-            lines,
-            parser: Parser::new()
+impl<'a> Evaluator<'a> {
+    pub fn new(checksum: &'a dyn Checksum, config: &ParserConfig) -> Evaluator<'a> {
+        Evaluator {
+            checksum,
+            digits: config.digits,
+            max_digit_distance: config.max_digit_distance,
+            max_entry_distance: config.max_entry_distance,
+            max_combinations: config.max_combinations,
+            font: config.reading_font(),
+        }
+    }
+
+    // Evaluate the status of an entry completed at the given line in the given field.  None if the
+    // entry is incomplete.
+    pub fn evaluate(&self, status: Status, line_number: usize, field: usize) -> Option<Result> {
+        let line_number = line_number as u32;
+        let field = field as u32;
+        match status {
+            Status::Success { account_number, segments, non_canonical } => {
+                if self.is_valid(&account_number) {
+                    self.success(account_number, line_number, field, non_canonical)
+                } else {
+                    let corrections = find_corrections(
+                        &segments,
+                        &self.font,
+                        self.max_digit_distance,
+                        self.max_entry_distance,
+                        self.max_combinations,
+                        self.checksum
+                    );
+                    self.bad_checksum(account_number, corrections, line_number, field, non_canonical)
+                }
+            }
+            Status::BadDigits { account_number, segments, uncertain, non_canonical } => {
+                let corrections = find_uncertain_corrections(
+                    &segments,
+                    &uncertain,
+                    &self.font,
+                    self.max_digit_distance,
+                    self.max_entry_distance,
+                    self.max_combinations,
+                    self.checksum
+                );
+                self.bad_digits(account_number, corrections, line_number, field, non_canonical)
+            }
+            Status::Error{kind, line_number, col, row} => {
+                self.error(
+                    kind,
+                    line_number as u32,
+                    field,
+                    col as u32,
+                    row as u32
+                )
+            }
+            Status::Errors { account_number, errors } => Some(Errors { account_number, errors, line_number, field, confidence: 0.0 }),
+            Status::Warning { kind, line_number } => Some(Warning { kind, line_number: line_number as u32, field }),
+            Status::Incomplete => None,
         }
     }
 
+    // Test if the account number passes the checksum
+    fn is_valid(&self, account_number : &str) -> bool {
+        is_checksum_valid(account_number, self.digits, self.checksum)
+    }
+
     // Create a Success result
-    fn success(&self, account_number : String) -> Option<Result> {
+    fn success(&self, account_number : String, line_number : u32, field : u32, non_canonical : bool) -> Option<Result> {
         Some(Success {
-           account_number,
-            line_number: self.parser.get_line_number() as u32,
+            account_number,
+            line_number,
+            field,
+            confidence: confidence(0, true, 1),
+            non_canonical,
         })
     }
 
-    // Create a BadChecksum result
-    fn bad_checksum(&self, account_number : String, alternates : Vec<String>) -> Option<Result> {
+    // Create a BadChecksum result.  Corrections must be ranked nearest first.
+    fn bad_checksum(&self, account_number : String, corrections : Vec<Correction>, line_number : u32, field : u32, non_canonical : bool) -> Option<Result> {
+        let confidence = match corrections.first() {
+            Some(nearest) => confidence(nearest.distance, true, corrections.len()),
+            None => confidence(0, false, 1),
+        };
         Some(BadChecksum {
             account_number,
-            line_number: self.parser.get_line_number() as u32,
-            alternates
+            line_number,
+            field,
+            alternates: corrections.into_iter().map(|correction| correction.account_number).collect(),
+            confidence,
+            non_canonical,
         })
     }
 
-    // Create a BadDigits result
-    fn bad_digits(&self, account_number : String, alternates : Vec<String>) -> Option<Result> {
+    // Create a BadDigits result.  Corrections must be ranked nearest first.
+    fn bad_digits(&self, account_number : String, corrections : Vec<Correction>, line_number : u32, field : u32, non_canonical : bool) -> Option<Result> {
+        // Without an alternate there is no complete account number to rely on
+        let confidence = match corrections.first() {
+            Some(nearest) => confidence(nearest.distance, true, corrections.len()),
+            None => 0.0,
+        };
         Some(BadDigits {
             account_number,
-            line_number: self.parser.get_line_number() as u32,
-            alternates
+            line_number,
+            field,
+            alternates: corrections.into_iter().map(|correction| correction.account_number).collect(),
+            confidence,
+            non_canonical,
         })
     }
 
     // Create an Error result
-    fn error(&self, message : String, line_number : u32, col : u32, row : u32) -> Option<Result> {
-        Some(Error { message, line_number, col, row})
+    fn error(&self, kind : ErrorKind, line_number : u32, field : u32, col : u32, row : u32) -> Option<Result> {
+        Some(Error { kind, line_number, field, col, row, confidence: 0.0 })
     }
 }
 
-impl<I> Iterator for Processor<I>
+// Estimate the likelihood that a reading is the actual account number, from the number of segments
+// flipped to reach it, whether it passes the checksum and how many readings compete with it
+fn confidence(flips : u32, checksum_passed : bool, competitors : usize) -> f64 {
+    let checksum = if checksum_passed { 1.0 } else { FAILED_CHECKSUM_PENALTY };
+    checksum * FLIP_PENALTY.powi(flips as i32) / competitors.max(1) as f64
+}
+
+// Reads the entries of every field from the input lines, in the order they are completed.  Yields
+// each status along with the line number it was completed at and its field.  Incomplete statuses
+// are dropped.  Shared by Processor and ParallelProcessor so they frame entries the same way.
+pub(crate) struct Entries<I>
     where I: Iterator<Item = String>
 {
-    type Item = Result;
+    // Iterator supplying input lines, cleaned up for the parser
+    lines: Normalizer<I>,
+
+    // Input parser for each field of a band of lines
+    parsers: Vec<Parser>,
+
+    // Statuses produced by the last line which have not been returned yet
+    statuses: VecDeque<(Status, usize, usize)>,
+
+    // Flag set once the end of the input has been reached
+    finished: bool,
+}
+
+impl<I> Entries<I>
+    where I: Iterator<Item = String>
+{
+    pub fn new(lines: I, config: &ParserConfig) -> Entries<I> {
+        Entries {
+            lines: Normalizer::new(lines, config.tab_width),
+            parsers: (0..config.fields.max(1)).map(|field| Parser::new(config.clone(), field)).collect(),
+            statuses: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    // Queue a status from the parser of a field along with any statuses it left pending
+    fn collect(&mut self, status: Status, field: usize) {
+        let line_number = self.parsers[field].get_line_number();
+        let pending = std::iter::from_fn(|| self.parsers[field].next_pending());
+        let statuses = std::iter::once((status, line_number)).chain(pending);
+        self.statuses.extend(
+            statuses
+                .filter(|(status, _)| !matches!(status, Status::Incomplete))
+                .map(|(status, line_number)| (status, line_number, field))
+        );
+    }
+}
+
+impl<I> Iterator for Entries<I>
+    where I: Iterator<Item = String>
+{
+    type Item = (Status, usize, usize);
 
     fn next(&mut self) -> Option<Self::Item> {
 
         loop {
+            if let Some(status) = self.statuses.pop_front() {
+                return Some(status);
+            }
+            if self.finished {
+                return None;
+            }
+
             let next = self.lines.next();
             match next {
                 Option::Some(line) => {
-                    let status = self.parser.process_line(&line);
-
-                    match status {
-                        Status::Success(account_number) => {
-                            if is_checksum_valid(&account_number) {
-                                return self.success(account_number)
-                            } else {
-                                let alternates = find_adjacent(&account_number);
-                                return self.bad_checksum(account_number, alternates);
-                            }
-                        }
-                        Status::BadDigits { account_number, alternates } => {
-                            return self.bad_digits(
-                                account_number,
-                                alternates
-                                    .into_iter()
-                                    .filter(|alt| is_checksum_valid(alt))
-                                    .collect()
-                            );
-                        }
-                        Status::Error{message, line_number, col, row} => {
-                            return self.error(
-                                message,
-                                line_number as u32,
-                                col as u32,
-                                row as u32
-                            );
-                        }
-                        Status::Incomplete => {
-                            // Keep going if parse of number is incomplete
-                        }
+                    // Keep going if parse of number is incomplete
+                    for field in 0..self.parsers.len() {
+                        let status = self.parsers[field].process_line(&line);
+                        self.collect(status, field);
                     }
                 }
 
-                Option::None => return Option::None
+                // Report entries left unfinished by the end of the input
+                Option::None => {
+                    self.finished = true;
+                    for field in 0..self.parsers.len() {
+                        let status = self.parsers[field].finish();
+                        self.collect(status, field);
+                    }
+                }
             }
         }
     }
 }
 
+// Transforms an input iterator into a processed output iterator
+pub struct Processor<'a, I>
+    where I: Iterator<Item = String>
+{
+    // Entries read from the input lines
+    entries: Entries<I>,
+
+    // Converts parsed entries into results
+    evaluator: Evaluator<'a>,
+}
+
+impl<'a, I> Processor<'a, I>
+    where I: Iterator<Item = String>
+{
+    // Create a processor for the standard 9 digit account numbers
+    pub fn new(lines: I, checksum: &'a dyn Checksum) -> Processor<'a, I>{
+        Processor::with_config(lines, checksum, ParserConfig::default())
+    }
+
+    // Create a processor with the given parser settings
+    pub fn with_config(lines: I, checksum: &'a dyn Checksum, config: ParserConfig) -> Processor<'a, I>{
+        Processor {
+            entries: Entries::new(lines, &config),
+            evaluator: Evaluator::new(checksum, &config),
+        }
+    }
+}
+
+impl<I> Iterator for Processor<'_, I>
+    where I: Iterator<Item = String>
+{
+    type Item = Result;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let evaluator = &self.evaluator;
+        self.entries.find_map(|(status, line_number, field)| evaluator.evaluate(status, line_number, field))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{AbaRouting, LeftMargin, Mod11, Mod17Hex};
 
     #[test]
     fn bad_checksum_with_alts() {
@@ -151,10 +346,10 @@ mod tests {
             "".to_string()
         ];
         let iter = input.iter().map(|s| s.to_string());
-        let output : Vec<Result> = Processor::new(iter).collect();
+        let output : Vec<Result> = Processor::new(iter, &Mod11).collect();
         assert_eq!(output.len(), 1);
 
-        if let BadChecksum { account_number, line_number, alternates} = &output[0] {
+        if let BadChecksum { account_number, line_number, alternates, ..} = &output[0] {
             assert_eq!(account_number, "490067715");
             assert_eq!(*line_number, 4);
             assert_eq!(*alternates, vec!["490867715", "490067115", "490067719"]);
@@ -172,10 +367,10 @@ mod tests {
                           "".to_string()
         ];
         let iter = input.iter().map(|s| s.to_string());
-        let output : Vec<Result> = Processor::new(iter).collect();
+        let output : Vec<Result> = Processor::new(iter, &Mod11).collect();
         assert_eq!(output.len(), 1);
 
-        if let BadChecksum { account_number, line_number, alternates} = &output[0] {
+        if let BadChecksum { account_number, line_number, alternates, ..} = &output[0] {
             assert_eq!(account_number, "723456789");
             assert_eq!(*line_number, 4);
             assert_eq!(*alternates, vec!["123456789"]);
@@ -193,10 +388,10 @@ mod tests {
             "".to_string()
         ];
         let iter = input.iter().map(|s| s.to_string());
-        let output : Vec<Result> = Processor::new(iter).collect();
+        let output : Vec<Result> = Processor::new(iter, &Mod11).collect();
         assert_eq!(output.len(), 1);
 
-        if let BadChecksum { account_number, line_number, alternates} = &output[0] {
+        if let BadChecksum { account_number, line_number, alternates, ..} = &output[0] {
             assert_eq!(account_number, "498061715");
             assert_eq!(*line_number, 4);
             assert_eq!(alternates.len(), 0);
@@ -214,10 +409,10 @@ mod tests {
             "".to_string()
         ];
         let iter = input.iter().map(|s| s.to_string());
-        let output : Vec<Result> = Processor::new(iter).collect();
+        let output : Vec<Result> = Processor::new(iter, &Mod11).collect();
         assert_eq!(output.len(), 1);
 
-        if let Success { account_number, line_number} = &output[0] {
+        if let Success { account_number, line_number, ..} = &output[0] {
             assert_eq!(account_number, "490067115");
             assert_eq!(*line_number, 4);
         } else {
@@ -225,4 +420,394 @@ mod tests {
         }
     }
 
+    #[test]
+    fn unfinished_entry_at_end_of_input() {
+        let input = [
+            "    _  _  _  _  _        _ ",
+            "|_||_|| || ||_   |  |  ||_ ",
+            "  | _||_||_||_|  |  |  | _|",
+            "",
+            "    _  _  _  _  _        _ ",
+        ];
+        let output : Vec<Result> = Processor::new(input.into_iter().map(String::from), &Mod11).collect();
+        assert_eq!(output.len(), 2);
+        assert!(matches!(output[1], Error { kind: ErrorKind::TruncatedEntry { rows: 1, .. }, line_number: 5, .. }));
+
+        // The separator after the last entry may be left out
+        let output : Vec<Result> = Processor::new(input[..3].iter().map(|s| s.to_string()), &Mod11).collect();
+        assert_eq!(output.len(), 1);
+        assert!(matches!(&output[0], Success { account_number, line_number: 3, .. } if account_number == "490067115"));
+    }
+
+    #[test]
+    fn realignment_is_reported_between_entries() {
+        let input = [
+            "    _  _  _  _  _        _ ",
+            "|_||_|| || ||_   |  |  ||_ ",
+            "  | _||_||_||_|  |  |  | _|",
+            "    _  _  _  _  _        _ ",
+            "|_||_|| || ||_   |  |  ||_ ",
+            "  | _||_||_||_|  |  |  | _|",
+            "",
+        ];
+        let output : Vec<Result> = Processor::new(input.into_iter().map(String::from), &Mod11).collect();
+        assert_eq!(output.len(), 3);
+        assert!(matches!(output[0], Success { line_number: 3, .. }));
+        assert!(matches!(output[1], Warning { kind: WarningKind::MissingSeparator, line_number: 4, .. }));
+        assert!(matches!(output[2], Success { line_number: 7, .. }));
+    }
+
+    #[test]
+    fn windows_line_endings_and_tabs() {
+        let input = [
+            "\t_  _  _  _  _        _ \r",
+            "|_||_|| || ||_   |  |  ||_ \r",
+            "  | _||_||_||_|  |  |  | _|    \r",
+            "\t\r",
+        ];
+        let config = ParserConfig { tab_width: 4, ..ParserConfig::default() };
+        let output : Vec<Result> = Processor::with_config(input.into_iter().map(String::from), &Mod11, config).collect();
+        assert_eq!(output.len(), 1);
+        assert!(matches!(&output[0], Success { account_number, line_number: 4, .. } if account_number == "490067115"));
+    }
+
+    #[test]
+    fn lone_carriage_returns_are_counted_as_line_breaks() {
+        let input = [
+            "    _  _  _  _  _        _ \r|_||_|| || ||_   |  |  ||_ \r  | _||_||_||_|  |  |  | _|\r",
+            "",
+            "    _  _  _  _  _        _ ",
+            "|_||_|| || ||_   |  |  ||_ ",
+            "  | _||_||_||_|  |  |  | _|",
+            "",
+        ];
+        let output : Vec<Result> = Processor::new(input.into_iter().map(String::from), &Mod11).collect();
+        assert_eq!(output.len(), 2);
+        assert!(matches!(output[0], Success { line_number: 4, .. }));
+        assert!(matches!(output[1], Success { line_number: 8, .. }));
+    }
+
+    #[test]
+    fn detected_left_margin() {
+        let config = ParserConfig { left_margin: LeftMargin::Auto, ..ParserConfig::default() };
+        let input = [
+            "       _  _  _  _  _        _ ",
+            "   |_||_|| || ||_   |  |  ||_ ",
+            "     | _||_||_||_|  |  |  | _|",
+            "",
+            "       _  _  _  _  _        _ ",
+        ];
+        let output : Vec<Result> = Processor::with_config(input.into_iter().map(String::from), &Mod11, config.clone()).collect();
+        assert_eq!(output.len(), 2);
+        assert!(matches!(&output[0], Success { account_number, .. } if account_number == "490067115"));
+        assert!(matches!(output[1], Error { kind: ErrorKind::TruncatedEntry { rows: 1, .. }, line_number: 5, col: 3, .. }));
+
+        // Input too short to hold an entry
+        let input = [
+            "    _  _  _  _  _        _ ",
+            "|_||_|| || ||_   |  |  ||_ ",
+        ];
+        let output : Vec<Result> = Processor::with_config(input.into_iter().map(String::from), &Mod11, config).collect();
+        assert_eq!(output.len(), 1);
+        assert!(matches!(output[0], Error { kind: ErrorKind::TruncatedEntry { rows: 2, .. }, line_number: 2, .. }));
+    }
+
+    #[test]
+    fn side_by_side_fields() {
+        let config = ParserConfig { fields: 2, field_gap: 2, ..ParserConfig::default() };
+        let input = [
+            "    _  _  _  _  _        _       _  _     _  _  _  _  _ ",
+            "|_||_|| || ||_   |  |  ||_     | _| _||_||_ |_   ||_||_|",
+            "  | _||_||_||_|  |  |  | _|    ||_  _|  | _||_|  ||_| _|",
+            "",
+            "    _  _  _  _  _        _       _  _     _  _  _  _  _ ",
+            "|_||_|| || ||_   |  |  ||_     | _| x||_||_ |_   ||_||_|",
+            "  | _||_||_||_|  |  |  | _|    ||_  _|  | _||_|  ||_| _|",
+            "",
+        ];
+        let output : Vec<Result> = Processor::with_config(input.into_iter().map(String::from), &Mod11, config).collect();
+        assert_eq!(output.len(), 4);
+        assert!(matches!(&output[0], Success { account_number, line_number: 4, field: 0, .. } if account_number == "490067115"));
+        assert!(matches!(&output[1], Success { account_number, line_number: 4, field: 1, .. } if account_number == "123456789"));
+        assert!(matches!(output[2], Error { line_number: 6, field: 1, col: 36, row: 1, .. }));
+        assert!(matches!(&output[3], Success { account_number, line_number: 8, field: 0, .. } if account_number == "490067115"));
+    }
+
+    #[test]
+    fn empty_fields_are_not_reported() {
+        let config = ParserConfig { fields: 2, field_gap: 2, ..ParserConfig::default() };
+        let input = [
+            "    _  _  _  _  _        _ ",
+            "|_||_|| || ||_   |  |  ||_ ",
+            "  | _||_||_||_|  |  |  | _|",
+            "",
+            "    _  _     _  _  _  _  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            "",
+        ];
+        let output : Vec<Result> = Processor::with_config(input.into_iter().map(String::from), &Mod11, config).collect();
+        assert_eq!(output.len(), 2);
+        assert!(matches!(&output[0], Success { account_number, line_number: 4, field: 0, .. } if account_number == "490067115"));
+        assert!(matches!(&output[1], Success { account_number, line_number: 8, field: 0, .. } if account_number == "123456789"));
+    }
+
+    #[test]
+    fn hexadecimal_entries_fail_a_decimal_checksum() {
+        let config = ParserConfig { font: Font::hexadecimal(), ..ParserConfig::default() };
+        let input = [
+            " _     _     _  _  _     _ ",
+            "|_||_ |   _||_ |_ | |  | _|",
+            "| ||_||_ |_||_ |  |_|  ||_ ",
+            "",
+        ];
+        let output : Vec<Result> = Processor::with_config(input.into_iter().map(String::from), &Mod11, config).collect();
+        assert_eq!(output.len(), 1);
+        assert!(matches!(&output[0], BadChecksum { account_number, .. } if account_number == "ABCDEF012"));
+    }
+
+    #[test]
+    fn hexadecimal_entries_pass_a_hexadecimal_checksum() {
+        let config = ParserConfig { font: Font::hexadecimal(), ..ParserConfig::default() };
+        let input = [
+            " _     _     _  _  _     _ ",
+            "|_||_ |   _||_ |_ | |  ||_|",
+            "| ||_||_ |_||_ |  |_|  | _|",
+            "",
+            " _     _     _  _  _     _ ",
+            "|_||_ |   _||_ |_ | |  ||_|",
+            "| ||_||_ |_||_ |  |_|  ||_|",
+            "",
+        ];
+        let output : Vec<Result> = Processor::with_config(input.into_iter().map(String::from), &Mod17Hex, config).collect();
+        assert_eq!(output.len(), 2);
+        assert!(matches!(&output[0], Success { account_number, .. } if account_number == "ABCDEF019"));
+        assert!(matches!(&output[1], BadChecksum { account_number, alternates, .. } if account_number == "ABCDEF018" && alternates == &["ABCDEF019"]));
+    }
+
+    #[test]
+    fn variant_glyphs_are_recorded() {
+        let config = ParserConfig { variants: true, ..ParserConfig::default() };
+        let input = [
+            "    _  _     _     _  _  _ ",
+            "  | _| _||_||_ |_ | ||_||_|",
+            "  ||_  _|  | _||_|  ||_|  |",
+            "",
+            "    _  _     _  _  _  _  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            "",
+        ];
+        let output : Vec<Result> = Processor::with_config(input.into_iter().map(String::from), &Mod11, config).collect();
+        assert_eq!(output.len(), 2);
+        assert!(matches!(&output[0], Success { account_number, non_canonical: true, .. } if account_number == "123456789"));
+        assert!(matches!(&output[1], Success { account_number, non_canonical: false, .. } if account_number == "123456789"));
+    }
+
+    #[test]
+    fn variant_glyphs_are_corrected_as_scanned() {
+        // 7 with an upper left stroke is one segment further from 1 than the canonical 7
+        let config = ParserConfig { variants: true, ..ParserConfig::default() };
+        let input = [
+            " _  _  _     _  _  _  _  _ ",
+            "| | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            "",
+        ];
+        let output : Vec<Result> = Processor::with_config(input.into_iter().map(String::from), &Mod11, config.clone()).collect();
+        assert!(matches!(&output[0], BadChecksum { account_number, alternates, non_canonical: true, .. }
+            if account_number == "723456789" && alternates.is_empty()));
+
+        let config = ParserConfig { max_digit_distance: 2, max_entry_distance: 2, ..config };
+        let output : Vec<Result> = Processor::with_config(input.into_iter().map(String::from), &Mod11, config).collect();
+        assert!(matches!(&output[0], BadChecksum { alternates, .. } if alternates.contains(&"123456789".to_string())));
+    }
+
+    #[test]
+    fn variant_glyphs_cost_no_flips_when_correcting_other_digits() {
+        // 490067715 with both 7s drawn with an upper left stroke
+        let config = ParserConfig { variants: true, ..ParserConfig::default() };
+        let input = [
+            "    _  _  _  _  _  _     _ ",
+            "|_||_|| || ||_ | || |  ||_ ",
+            "  | _||_||_||_|  |  |  | _|",
+            "",
+        ];
+        let output : Vec<Result> = Processor::with_config(input.into_iter().map(String::from), &Mod11, config).collect();
+        assert_eq!(output.len(), 1);
+        assert!(matches!(&output[0], BadChecksum { account_number, alternates, non_canonical: true, .. }
+            if account_number == "490067715" && *alternates == ["490867715", "490067719"]));
+    }
+
+    #[test]
+    fn configured_digit_count() {
+        let input = [
+            " _  _  _  _  _  _     _ ".to_string(),
+            "| || || || || || |  ||_|".to_string(),
+            "|_||_||_||_||_||_|  | _|".to_string(),
+            "".to_string()
+        ];
+        let iter = input.iter().map(|s| s.to_string());
+        let output : Vec<Result> = Processor::with_config(iter, &Mod11, ParserConfig { digits: 8, ..ParserConfig::default() }).collect();
+        assert_eq!(output.len(), 1);
+
+        if let Success { account_number, line_number, ..} = &output[0] {
+            assert_eq!(account_number, "00000019");
+            assert_eq!(*line_number, 4);
+        } else {
+            panic!("Not Success variant")
+        }
+    }
+
+    #[test]
+    fn alternate_checksum() {
+        let input = [ // 011000015
+            " _     _  _  _  _  _     _ ".to_string(),
+            "| |  |  || || || || |  ||_ ".to_string(),
+            "|_|  |  ||_||_||_||_|  | _|".to_string(),
+            "".to_string()
+        ];
+        let iter = input.iter().map(|s| s.to_string());
+        let output : Vec<Result> = Processor::new(iter.clone(), &AbaRouting).collect();
+        if let BadChecksum { account_number, alternates, ..} = &output[0] {
+            assert_eq!(account_number, "017000015");
+            assert_eq!(*alternates, vec!["817000015", "011000015", "017800015", "017000815", "017000019"]);
+        } else {
+            panic!("Not BadChecksum variant")
+        }
+
+        let output : Vec<Result> = Processor::new(iter, &Mod11).collect();
+        if let BadChecksum { account_number, alternates, ..} = &output[0] {
+            assert_eq!(account_number, "017000015");
+            assert_eq!(*alternates, vec!["011000015", "017000815"]);
+        } else {
+            panic!("Not BadChecksum variant")
+        }
+    }
+
+    #[test]
+    fn confidence_reflects_flips_checksum_and_competitors() {
+        let input = [
+            "    _  _  _  _  _        _ ", // 490067115
+            "|_||_|| || ||_   |  |  ||_ ",
+            "  | _||_||_||_|  |  |  | _|",
+            "",
+            " _  _  _     _  _  _  _  _ ", // 723456789
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+            "",
+            "    _  _  _  _  _  _     _ ", // 490067715
+            "|_||_|| || ||_   |  |  ||_ ",
+            "  | _||_||_||_|  |  |  | _|",
+            "",
+            "    _  _  _  _     _     _ ", // 498061715
+            "|_||_||_|| ||_   |  |  ||_ ",
+            "  | _||_||_||_|  |  |  | _|",
+            "",
+            "    _  _  _  _  _        _ ", // 49006?115
+            "|_||_|| || ||    |  |  ||_ ",
+            "  | _||_||_||_|  |  |  | _|",
+            "",
+            "    _  _     _  _  _  _  _ ", // 1234?678?
+            "  | _| _||_| _ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _ ",
+            "",
+            "    _  _  _     _        _ ", // 4900?7115
+            "|_||_|| || ||_   |  |  ||_ ",
+            "  | _||_||_| _   |  |  | _|",
+            "",
+        ];
+        let confidences = |input : &[&str], config| -> Vec<f64> {
+            let iter = input.iter().map(|s| s.to_string());
+            Processor::with_config(iter, &Mod11, config).map(|result| match result {
+                Success { confidence, .. } => confidence,
+                BadChecksum { confidence, .. } => confidence,
+                BadDigits { confidence, .. } => confidence,
+                Error { confidence, .. } => confidence,
+                Errors { confidence, .. } => confidence,
+                Warning { .. } => 0.0,
+            }).collect()
+        };
+        assert_eq!(confidences(&input, ParserConfig::default()), vec![1.0, 0.75, 0.25, 0.1, 0.75, 0.28125, 0.0]);
+
+        // Reading both illegible digits of 1234?678? flips two segments, and without flips of the
+        // other digits it has no competitor
+        let config = ParserConfig { max_entry_distance: 0, ..ParserConfig::default() };
+        assert_eq!(confidences(&input[20..24], config), vec![0.5625]);
+    }
+
+    #[test]
+    fn multi_segment_correction() {
+        let input = [ // 723456788
+            " _  _  _     _  _  _  _  _ ".to_string(),
+            "  | _| _||_||_ |_   ||_||_|".to_string(),
+            "  ||_  _|  | _||_|  ||_||_|".to_string(),
+            "".to_string()
+        ];
+        let output : Vec<Result> = Processor::new(input.clone().into_iter(), &Mod11).collect();
+        if let BadChecksum { alternates, .. } = &output[0] {
+            assert!(!alternates.contains(&"123456789".to_string()));
+        } else {
+            panic!("Not BadChecksum variant")
+        }
+
+        let config = ParserConfig { max_entry_distance: 2, ..ParserConfig::default() };
+        let output : Vec<Result> = Processor::with_config(input.into_iter(), &Mod11, config).collect();
+        if let BadChecksum { account_number, alternates, confidence, .. } = &output[0] {
+            assert_eq!(account_number, "723456788");
+            assert!(alternates.contains(&"123456789".to_string()));
+            assert!(*confidence < 0.75);
+        } else {
+            panic!("Not BadChecksum variant")
+        }
+    }
+
+    #[test]
+    fn every_combination_of_illegible_digits_is_tried() {
+        let input = [ // 1234?678? where each illegible digit is one segment from a digit
+            "    _  _     _  _  _  _  _ ",
+            "  | _| _||_| _ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _ ",
+            "",
+        ];
+        let alternates = |config| -> Vec<String> {
+            let mut output = Processor::with_config(input.into_iter().map(String::from), &Mod11, config);
+            match output.next() {
+                Some(BadDigits { alternates, .. }) => alternates,
+                _ => panic!("Not BadDigits variant"),
+            }
+        };
+
+        // Flips of the digits which were read are still limited by the entry distance
+        assert_eq!(alternates(ParserConfig::default()), vec!["123456789", "723436789"]);
+        assert_eq!(alternates(ParserConfig { max_entry_distance: 0, ..ParserConfig::default() }), vec!["123456789"]);
+        assert_eq!(alternates(ParserConfig { max_digit_distance: 0, ..ParserConfig::default() }), Vec::<String>::new());
+    }
+
+    #[test]
+    fn multi_segment_correction_of_illegible_digit() {
+        let input = [ // 4900?7115 where the 6 has lost its top and bottom segments
+            "    _  _  _     _        _ ".to_string(),
+            "|_||_|| || ||_   |  |  ||_ ".to_string(),
+            "  | _||_||_|| |  |  |  | _|".to_string(),
+            "".to_string()
+        ];
+        let output : Vec<Result> = Processor::new(input.clone().into_iter(), &Mod11).collect();
+        if let BadDigits { account_number, alternates, .. } = &output[0] {
+            assert_eq!(account_number, "4900?7115");
+            assert!(alternates.is_empty());
+        } else {
+            panic!("Not BadDigits variant")
+        }
+
+        // The flips of an illegible digit do not count towards the entry distance
+        let config = ParserConfig { max_digit_distance: 2, max_entry_distance: 0, ..ParserConfig::default() };
+        let output : Vec<Result> = Processor::with_config(input.into_iter(), &Mod11, config).collect();
+        if let BadDigits { alternates, .. } = &output[0] {
+            assert_eq!(*alternates, vec!["490067115"]);
+        } else {
+            panic!("Not BadDigits variant")
+        }
+    }
+
 }
\ No newline at end of file
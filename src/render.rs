@@ -0,0 +1,100 @@
+use crate::{bit_pos, digit_segments, on_char};
+
+// Render an account number as the three rows of glyphs read by the parser.
+// Characters other than '0' through '9' are rendered as a blank cell, which the parser reads as
+// an illegible digit.
+pub fn render(account_number : &str) -> [String; 3] {
+    let register : Vec<u8> = account_number.bytes().map(|ch| digit_segments(ch).unwrap_or(0)).collect();
+    render_segments(&register)
+}
+
+// Render the segments of each digit as the three rows of glyphs read by the parser
+pub fn render_segments(register : &[u8]) -> [String; 3] {
+    let mut rows = [String::new(), String::new(), String::new()];
+    for segments in register {
+        for (row, output) in rows.iter_mut().enumerate() {
+            for col in 0..3 {
+                let on = on_char(row, col);
+                if on != '\0' && segments & (1 << bit_pos(row, col)) != 0 {
+                    output.push(on);
+                } else {
+                    output.push(' ');
+                }
+            }
+        }
+    }
+    rows
+}
+
+// Render an account number at a larger scale.  Each digit is 3 * scale columns wide and
+// 2 * scale + 1 rows high, with its strokes drawn as runs of scale characters.
+pub fn render_scaled(account_number : &str, scale : usize) -> Vec<String> {
+    let scale = scale.max(1);
+    let rows = render(account_number);
+    let mut scaled = vec![stretch(&rows[0], scale, true)];
+    for row in &rows[1..] {
+        // Bars are only drawn on the last text row of each half of the glyph
+        for text_row in 1..=scale {
+            scaled.push(stretch(row, scale, text_row == scale));
+        }
+    }
+    scaled
+}
+
+// Repeat each character of a row of glyphs to fill the columns of a larger scale
+fn stretch(row : &str, scale : usize, bars : bool) -> String {
+    row.chars()
+        .enumerate()
+        .flat_map(|(col, ch)| {
+            let ch = if col % 3 == 1 && !bars { ' ' } else { ch };
+            std::iter::repeat_n(ch, scale)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Mod11, Processor, Result};
+
+    #[test]
+    fn renders_glyphs() {
+        assert_eq!(render("123456789"), [
+            "    _  _     _  _  _  _  _ ",
+            "  | _| _||_||_ |_   ||_||_|",
+            "  ||_  _|  | _||_|  ||_| _|",
+        ]);
+
+        assert_eq!(render("0?"), [
+            " _    ",
+            "| |   ",
+            "|_|   ",
+        ]);
+    }
+
+    #[test]
+    fn renders_scaled_glyphs() {
+        assert_eq!(render_scaled("08", 2), [
+            "  __    __  ",
+            "||  ||||  ||",
+            "||  ||||__||",
+            "||  ||||  ||",
+            "||__||||__||",
+        ]);
+        assert_eq!(render_scaled("1", 1), render("1"));
+    }
+
+    #[test]
+    fn rendered_glyphs_can_be_parsed() {
+        for account_number in ["000000051", "490067115", "345882865"] {
+            let [top, middle, bottom] = render(account_number);
+            let input = [top, middle, bottom, String::new()];
+            let output : Vec<Result> = Processor::new(input.into_iter(), &Mod11).collect();
+            if let Result::Success { account_number: parsed, .. } = &output[0] {
+                assert_eq!(parsed, account_number);
+            } else {
+                panic!("Not Success variant")
+            }
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use std::fmt;
+use crate::{ParseError, Result, WarningKind};
+
+// Tally of the results of a run
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Summary {
+    // Entries which were read and passed the checksum
+    pub success: usize,
+
+    // Entries which were read but failed the checksum
+    pub bad_checksum: usize,
+
+    // Entries with illegible digits
+    pub bad_digits: usize,
+
+    // Entries which could not be parsed
+    pub error: usize,
+
+    // Times the framing of entries was reset after misaligned input
+    pub warning: usize,
+}
+
+impl Summary {
+    // Count a result
+    pub fn add(&mut self, line : &Result) {
+        match line {
+            Result::Success {..} => self.success += 1,
+            Result::BadChecksum {..} => self.bad_checksum += 1,
+            Result::BadDigits {..} => self.bad_digits += 1,
+            Result::Error {..} | Result::Errors {..} => self.error += 1,
+            Result::Warning {..} => self.warning += 1,
+        }
+    }
+
+    // Total number of entries counted, not including warnings
+    pub fn total(&self) -> usize {
+        self.success + self.bad_checksum + self.bad_digits + self.error
+    }
+
+    // Number of entries which were read but need to be checked by a person
+    pub fn needs_review(&self) -> usize {
+        self.bad_checksum + self.bad_digits
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} entries: {} valid, {} bad checksum, {} illegible, {} errors, {} warnings",
+            self.total(), self.success, self.bad_checksum, self.bad_digits, self.error, self.warning
+        )
+    }
+}
+
+// Column headings for the CSV report.  New columns go at the end so that spreadsheets built on
+// earlier reports keep working.
+pub const CSV_HEADER : &str = "line,status,account_number,alternates,message,col,row,confidence,kind,field,non_canonical";
+
+// Format a result from Process as a CSV row matching CSV_HEADER.  Collected errors are written
+// as one row each.
+pub fn format_csv(line : Result) -> String {
+    let columns : [String; 11] = match line {
+        Result::Success {account_number, line_number, field, confidence, non_canonical} =>
+            [line_number.to_string(), "success".to_string(), account_number, String::new(), String::new(), String::new(), String::new(), format_confidence(confidence), String::new(), field.to_string(), non_canonical.to_string()],
+        Result::BadChecksum {account_number, line_number, field, alternates, confidence, non_canonical} =>
+            [line_number.to_string(), "bad_checksum".to_string(), account_number, alternates.join(" "), String::new(), String::new(), String::new(), format_confidence(confidence), String::new(), field.to_string(), non_canonical.to_string()],
+        Result::BadDigits {account_number, line_number, field, alternates, confidence, non_canonical} =>
+            [line_number.to_string(), "bad_digits".to_string(), account_number, alternates.join(" "), String::new(), String::new(), String::new(), format_confidence(confidence), String::new(), field.to_string(), non_canonical.to_string()],
+        Result::Error {kind, line_number, field, col, row, confidence} =>
+            [line_number.to_string(), "error".to_string(), String::new(), String::new(), kind.to_string(), col.to_string(), row.to_string(), format_confidence(confidence), kind.name().to_string(), field.to_string(), String::new()],
+        Result::Errors {account_number, errors, line_number, field, confidence} => {
+            return errors
+                .iter()
+                .map(|error| {
+                    let columns = [line_number.to_string(), "error".to_string(), account_number.clone(), String::new(), error.kind.to_string(), error.col.to_string(), error.row.to_string(), format_confidence(confidence), error.kind.name().to_string(), field.to_string(), String::new()];
+                    csv_row(&columns)
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+        }
+        Result::Warning {kind, line_number, field} =>
+            [line_number.to_string(), "warning".to_string(), String::new(), String::new(), kind.to_string(), String::new(), String::new(), String::new(), kind.name().to_string(), field.to_string(), String::new()],
+    };
+    csv_row(&columns)
+}
+
+// Join quoted fields into a CSV row
+fn csv_row(columns : &[String]) -> String {
+    columns.iter().map(|column| csv_field(column)).collect::<Vec<String>>().join(",")
+}
+
+// Format a result from Process as a single JSON object
+pub fn format_jsonl(line : Result) -> String {
+    let row = match &line {
+        Result::Success {account_number, line_number, field, confidence, non_canonical} =>
+            JsonRow {
+                status: "success", account_number: Some(account_number), line_number: *line_number, field: *field,
+                confidence: *confidence, non_canonical: *non_canonical, ..JsonRow::default()
+            },
+        Result::BadChecksum {account_number, line_number, field, alternates, confidence, non_canonical} =>
+            JsonRow {
+                status: "bad_checksum", account_number: Some(account_number), alternates, line_number: *line_number,
+                field: *field, confidence: *confidence, non_canonical: *non_canonical, ..JsonRow::default()
+            },
+        Result::BadDigits {account_number, line_number, field, alternates, confidence, non_canonical} =>
+            JsonRow {
+                status: "bad_digits", account_number: Some(account_number), alternates, line_number: *line_number,
+                field: *field, confidence: *confidence, non_canonical: *non_canonical, ..JsonRow::default()
+            },
+        Result::Error {kind, line_number, field, col, row, confidence} => {
+            let error = ParseError { kind: kind.clone(), line_number: *line_number as usize, col: *col as usize, row: *row as usize };
+            JsonRow {
+                status: "error", line_number: *line_number, field: *field, confidence: *confidence,
+                errors: vec![json_error(&error)], ..JsonRow::default()
+            }
+        }
+        Result::Errors {account_number, errors, line_number, field, confidence} =>
+            JsonRow {
+                status: "error", account_number: Some(account_number), line_number: *line_number, field: *field,
+                confidence: *confidence, errors: errors.iter().map(json_error).collect(), ..JsonRow::default()
+            },
+        Result::Warning {kind, line_number, field} =>
+            JsonRow { status: "warning", line_number: *line_number, field: *field, warning: Some(kind), ..JsonRow::default() },
+    };
+    row.to_json()
+}
+
+// Format the details of an error as a JSON object
+fn json_error(error : &ParseError) -> String {
+    format!(
+        "{{\"kind\":{},\"message\":{},\"col\":{},\"row\":{}}}",
+        json_string(error.kind.name()), json_string(&error.kind.to_string()), error.col, error.row
+    )
+}
+
+// Values for the JSON object shared by every result
+#[derive(Default)]
+struct JsonRow<'a> {
+    status: &'a str,
+    account_number: Option<&'a str>,
+    alternates: &'a [String],
+    line_number: u32,
+    field: u32,
+    confidence: f64,
+    non_canonical: bool,
+
+    // Collected errors, already formatted as JSON objects
+    errors: Vec<String>,
+    warning: Option<&'a WarningKind>,
+}
+
+impl JsonRow<'_> {
+    // Build the JSON object.  All keys are always present so that consumers do not need to
+    // test for them.  The error key holds the first of the errors.
+    fn to_json(&self) -> String {
+        let warning = self.warning.map_or("null".to_string(), |kind| {
+            format!("{{\"kind\":{},\"message\":{}}}", json_string(kind.name()), json_string(&kind.to_string()))
+        });
+        let alternates : Vec<String> = self.alternates.iter().map(|alt| json_string(alt)).collect();
+        format!(
+            "{{\"status\":{},\"account_number\":{},\"alternates\":[{}],\"line_number\":{},\"field\":{},\"confidence\":{},\"non_canonical\":{},\"error\":{},\"errors\":[{}],\"warning\":{}}}",
+            json_string(self.status),
+            self.account_number.map_or("null".to_string(), json_string),
+            alternates.join(","),
+            self.line_number,
+            self.field,
+            format_confidence(self.confidence),
+            self.non_canonical,
+            self.errors.first().map_or("null", String::as_str),
+            self.errors.join(","),
+            warning
+        )
+    }
+}
+
+// Format a confidence with enough precision to rank entries
+fn format_confidence(confidence : f64) -> String {
+    format!("{:.3}", confidence)
+}
+
+// Quote and escape a string for JSON
+fn json_string(value : &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Quote a CSV field if it contains a delimiter, quote, line break or surrounding whitespace
+fn csv_field(value : &str) -> String {
+    let needs_quotes = value.contains([',', '"', '\n', '\r'])
+        || value.starts_with(' ')
+        || value.ends_with(' ');
+    if needs_quotes {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    #[test]
+    fn formats_each_result_as_json() {
+        assert_eq!(
+            format_jsonl(Result::Success { account_number: "490067115".to_string(), line_number: 4, field: 0, confidence: 1.0, non_canonical: true }),
+            r#"{"status":"success","account_number":"490067115","alternates":[],"line_number":4,"field":0,"confidence":1.000,"non_canonical":true,"error":null,"errors":[],"warning":null}"#
+        );
+
+        assert_eq!(
+            format_jsonl(Result::BadChecksum {
+                account_number: "490067715".to_string(),
+                alternates: vec!["490867715".to_string(), "490067115".to_string()],
+                line_number: 8, field: 0,
+                confidence: 0.375,
+                non_canonical: false
+            }),
+            r#"{"status":"bad_checksum","account_number":"490067715","alternates":["490867715","490067115"],"line_number":8,"field":0,"confidence":0.375,"non_canonical":false,"error":null,"errors":[],"warning":null}"#
+        );
+
+        assert_eq!(
+            format_jsonl(Result::BadDigits {
+                account_number: "49006771?".to_string(),
+                alternates: vec![],
+                line_number: 12, field: 0,
+                confidence: 0.0,
+                non_canonical: false
+            }),
+            r#"{"status":"bad_digits","account_number":"49006771?","alternates":[],"line_number":12,"field":0,"confidence":0.000,"non_canonical":false,"error":null,"errors":[],"warning":null}"#
+        );
+
+        assert_eq!(
+            format_jsonl(Result::Error {
+                kind: ErrorKind::UnexpectedChar { found: '"', expected: vec![' ', '_'] },
+                line_number: 14, field: 0,
+                col: 7,
+                row: 1,
+                confidence: 0.0
+            }),
+            r#"{"status":"error","account_number":null,"alternates":[],"line_number":14,"field":0,"confidence":0.000,"non_canonical":false,"error":{"kind":"unexpected_char","message":"Expected space or '_' but found '\"'.","col":7,"row":1},"errors":[{"kind":"unexpected_char","message":"Expected space or '_' but found '\"'.","col":7,"row":1}],"warning":null}"#
+        );
+    }
+
+    #[test]
+    fn formats_collected_errors() {
+        let errors = || Result::Errors {
+            account_number: "12?45678?".to_string(),
+            errors: vec![
+                ParseError { kind: ErrorKind::UnexpectedChar { found: 'x', expected: vec![' ', '_'] }, line_number: 2, col: 7, row: 1 },
+                ParseError { kind: ErrorKind::LineTooLong { found: '|', max_len: 27 }, line_number: 3, col: 28, row: 2 },
+            ],
+            line_number: 4, field: 0,
+            confidence: 0.0,
+        };
+
+        assert_eq!(
+            format_jsonl(errors()),
+            r#"{"status":"error","account_number":"12?45678?","alternates":[],"line_number":4,"field":0,"confidence":0.000,"non_canonical":false,"error":{"kind":"unexpected_char","message":"Expected space or '_' but found 'x'.","col":7,"row":1},"errors":[{"kind":"unexpected_char","message":"Expected space or '_' but found 'x'.","col":7,"row":1},{"kind":"line_too_long","message":"Input line is too long.","col":28,"row":2}],"warning":null}"#
+        );
+
+        assert_eq!(
+            format_csv(errors()),
+            "4,error,12?45678?,,Expected space or '_' but found 'x'.,7,1,0.000,unexpected_char,0,\n\
+             4,error,12?45678?,,Input line is too long.,28,2,0.000,line_too_long,0,"
+        );
+    }
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), r#""a\"b\\c\nd\u0001""#);
+    }
+
+    #[test]
+    fn formats_each_result_as_csv() {
+        // The original columns keep their positions
+        assert!(CSV_HEADER.starts_with("line,status,account_number,alternates,message,col,row,"));
+
+        assert_eq!(
+            format_csv(Result::Success { account_number: "490067115".to_string(), line_number: 4, field: 0, confidence: 1.0, non_canonical: false }),
+            "4,success,490067115,,,,,1.000,,0,false"
+        );
+
+        assert_eq!(
+            format_csv(Result::BadChecksum {
+                account_number: "490067715".to_string(),
+                alternates: vec!["490867715".to_string(), "490067115".to_string()],
+                line_number: 8, field: 0,
+                confidence: 0.375,
+                non_canonical: false
+            }),
+            "8,bad_checksum,490067715,490867715 490067115,,,,0.375,,0,false"
+        );
+
+        assert_eq!(
+            format_csv(Result::BadDigits {
+                account_number: "49006771?".to_string(),
+                alternates: vec![],
+                line_number: 12, field: 0,
+                confidence: 0.0,
+                non_canonical: false
+            }),
+            "12,bad_digits,49006771?,,,,,0.000,,0,false"
+        );
+
+        assert_eq!(
+            format_csv(Result::Error {
+                kind: ErrorKind::UnexpectedChar { found: ',', expected: vec![' ', '_'] },
+                line_number: 14, field: 0,
+                col: 7,
+                row: 1,
+                confidence: 0.0
+            }),
+            "14,error,,,\"Expected space or '_' but found ','.\",7,1,0.000,unexpected_char,0,"
+        );
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("a\nb"), "\"a\nb\"");
+        assert_eq!(csv_field(" padded"), "\" padded\"");
+    }
+
+    #[test]
+    fn tallies_results() {
+        let mut summary = Summary::default();
+        summary.add(&Result::Success { account_number: "490067115".to_string(), line_number: 4, field: 0, confidence: 1.0, non_canonical: false });
+        summary.add(&Result::Success { account_number: "490067115".to_string(), line_number: 8, field: 0, confidence: 1.0, non_canonical: false });
+        summary.add(&Result::BadDigits { account_number: "49006771?".to_string(), alternates: vec![], line_number: 12, field: 0, confidence: 0.0, non_canonical: false });
+        summary.add(&Result::Error { kind: ErrorKind::NonBlankSeparator { found: 'x' }, line_number: 14, field: 0, col: 7, row: 1, confidence: 0.0 });
+        summary.add(&Result::Warning { kind: WarningKind::MissingSeparator, line_number: 15, field: 0 });
+
+        assert_eq!(summary, Summary { success: 2, bad_checksum: 0, bad_digits: 1, error: 1, warning: 1 });
+        assert_eq!(summary.total(), 4);
+        assert_eq!(summary.needs_review(), 1);
+        assert_eq!(summary.to_string(), "4 entries: 2 valid, 0 bad checksum, 1 illegible, 1 errors, 1 warnings");
+    }
+}